@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+
+use geo::{Coord, LineString};
+
+/// Arcs flatter than this are just lines as far as the plotter cares.
+const MAX_ARC_RADIUS: f64 = 5000.;
+/// Don't bother with arcs made of fewer segments than this.
+const MIN_ARC_SEGMENTS: usize = 3;
+/// Keep arcs well short of a full circle so the I/J form stays unambiguous.
+const MAX_ARC_SWEEP: f64 = 1.5 * PI;
+
+/// One move after the start of a linestring, either a straight line to a
+/// point, or an arc around a center.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    Line(Coord<f64>),
+    Arc {
+        end: Coord<f64>,
+        center: Coord<f64>,
+        clockwise: bool,
+        /// How many line segments this arc replaced.
+        segments: usize,
+    },
+}
+
+impl PathSegment {
+    pub fn end(&self) -> Coord<f64> {
+        match self {
+            PathSegment::Line(end) => *end,
+            PathSegment::Arc { end, .. } => *end,
+        }
+    }
+}
+
+fn cross(o: &Coord<f64>, a: &Coord<f64>, b: &Coord<f64>) -> f64 {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+fn distance(a: &Coord<f64>, b: &Coord<f64>) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// Center of the circle through three points, if they aren't colinear.
+fn circle_center(a: &Coord<f64>, b: &Coord<f64>, c: &Coord<f64>) -> Option<Coord<f64>> {
+    let d = 2. * (a.x * (b.y - c.y) + b.x * (c.y - a.y) + c.x * (a.y - b.y));
+    if d.abs() < 1e-12 {
+        return None;
+    }
+    let a2 = a.x * a.x + a.y * a.y;
+    let b2 = b.x * b.x + b.y * b.y;
+    let c2 = c.x * c.x + c.y * c.y;
+    Some(Coord {
+        x: (a2 * (b.y - c.y) + b2 * (c.y - a.y) + c2 * (a.y - b.y)) / d,
+        y: (a2 * (c.x - b.x) + b2 * (a.x - c.x) + c2 * (b.x - a.x)) / d,
+    })
+}
+
+/// Checks whether points[start..=end] all sit on one arc within tolerance,
+/// turning consistently the same way. Returns the center and direction.
+fn fit(
+    points: &[Coord<f64>],
+    start: usize,
+    end: usize,
+    tolerance: f64,
+) -> Option<(Coord<f64>, bool)> {
+    let mid = (start + end) / 2;
+    let center = circle_center(&points[start], &points[mid], &points[end])?;
+    let radius = distance(&center, &points[start]);
+    if radius > MAX_ARC_RADIUS {
+        return None;
+    }
+    let clockwise = cross(&points[start], &points[mid], &points[end]) < 0.;
+    let mut sweep = 0.;
+    for pair in points[start..=end].windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if (distance(&center, b) - radius).abs() > tolerance {
+            return None;
+        }
+        // The chord midpoint can't bulge away from the arc either.
+        let chord_mid = Coord {
+            x: (a.x + b.x) / 2.,
+            y: (a.y + b.y) / 2.,
+        };
+        if radius - distance(&center, &chord_mid) > tolerance {
+            return None;
+        }
+        let turn = cross(&center, a, b);
+        if turn == 0. || (turn < 0.) != clockwise {
+            return None;
+        }
+        let angle_a = (a.y - center.y).atan2(a.x - center.x);
+        let angle_b = (b.y - center.y).atan2(b.x - center.x);
+        let mut step = (angle_b - angle_a).abs();
+        if step > PI {
+            step = 2. * PI - step;
+        }
+        sweep += step;
+    }
+    if sweep > MAX_ARC_SWEEP {
+        return None;
+    }
+    Some((center, clockwise))
+}
+
+/// Walks a linestring and replaces runs of points that lie on a circular
+/// arc (within tolerance) with a single arc move. Everything else comes
+/// back as plain lines. The first point of the linestring is not included,
+/// since that's where the pen already is.
+pub fn fit_arcs(line: &LineString<f64>, tolerance: f64) -> Vec<PathSegment> {
+    let points = &line.0;
+    let mut out: Vec<PathSegment> = Vec::new();
+    let mut start = 0usize;
+    while start + 1 < points.len() {
+        let mut best: Option<(usize, Coord<f64>, bool)> = None;
+        let mut end = start + MIN_ARC_SEGMENTS;
+        while end < points.len() {
+            match fit(points, start, end, tolerance) {
+                Some((center, clockwise)) => best = Some((end, center, clockwise)),
+                None => break,
+            }
+            end += 1;
+        }
+        match best {
+            Some((end, center, clockwise)) => {
+                out.push(PathSegment::Arc {
+                    end: points[end],
+                    center,
+                    clockwise,
+                    segments: end - start,
+                });
+                start = end;
+            }
+            None => {
+                out.push(PathSegment::Line(points[start + 1]));
+                start += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::coord;
+
+    fn circle_points(count: usize, sweep: f64, clockwise: bool) -> LineString<f64> {
+        LineString::new(
+            (0..=count)
+                .map(|i| {
+                    let mut angle = sweep * (i as f64) / (count as f64);
+                    if clockwise {
+                        angle = -angle;
+                    }
+                    coord! {x: 10. + 5. * angle.cos(), y: 20. + 5. * angle.sin()}
+                })
+                .collect::<Vec<Coord<f64>>>(),
+        )
+    }
+
+    #[test]
+    fn test_fit_arc_ccw() {
+        let segments = fit_arcs(&circle_points(40, PI, false), 0.01);
+        assert_eq!(segments.len(), 1);
+        match &segments[0] {
+            PathSegment::Arc {
+                end,
+                center,
+                clockwise,
+                segments,
+            } => {
+                assert!((center.x - 10.).abs() < 1e-6 && (center.y - 20.).abs() < 1e-6);
+                assert!((end.x - 5.).abs() < 1e-6);
+                assert!(!clockwise);
+                assert_eq!(*segments, 40);
+            }
+            _ => panic!("Expected an arc"),
+        }
+    }
+
+    #[test]
+    fn test_fit_arc_cw_and_full_circle_split() {
+        let segments = fit_arcs(&circle_points(80, 2. * PI, true), 0.01);
+        assert!(segments.len() >= 2);
+        assert!(segments.iter().all(|s| matches!(
+            s,
+            PathSegment::Arc {
+                clockwise: true,
+                ..
+            }
+        )));
+        assert_eq!(
+            segments.last().unwrap().end(),
+            circle_points(80, 2. * PI, true).0[80]
+        );
+    }
+
+    #[test]
+    fn test_straight_lines_stay_lines() {
+        let line = LineString::new(vec![
+            coord! {x: 0., y: 0.},
+            coord! {x: 1., y: 0.},
+            coord! {x: 2., y: 0.},
+            coord! {x: 3., y: 0.},
+            coord! {x: 3., y: 3.},
+        ]);
+        let segments = fit_arcs(&line, 0.01);
+        assert_eq!(segments.len(), 4);
+        assert!(segments.iter().all(|s| matches!(s, PathSegment::Line(_))));
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// What the marks are drawn around.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub enum MarksTarget {
+    #[default]
+    Extents,
+    Paper,
+}
+
+impl MarksTarget {
+    pub fn all() -> Vec<MarksTarget> {
+        vec![MarksTarget::Extents, MarksTarget::Paper]
+    }
+}
+
+impl Display for MarksTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarksTarget::Extents => write!(f, "Extents"),
+            MarksTarget::Paper => write!(f, "Paper"),
+        }
+    }
+}
+
+/// Crop and registration marks, kept on the project so they can be redrawn
+/// whenever the artwork moves.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct MarksConfig {
+    pub target: MarksTarget,
+    /// The pen to draw the marks with. The first pen if unset.
+    #[serde(default)]
+    pub pen_uuid: Option<Uuid>,
+    /// How far (mm) outside the target the trim line is.
+    pub bleed: f64,
+    pub crop_marks: bool,
+    /// How long (mm) each crop mark is.
+    pub crop_length: f64,
+    /// The gap (mm) between the trim corner and its crop marks.
+    pub crop_offset: f64,
+    /// A registration target centered on each side of the trim line.
+    pub crosshairs: bool,
+    /// How far (mm) each arm of a crosshair reaches from its center.
+    pub crosshair_size: f64,
+    /// Draws the trim line itself.
+    pub bleed_box: bool,
+}
+
+impl Default for MarksConfig {
+    fn default() -> Self {
+        Self {
+            target: MarksTarget::default(),
+            pen_uuid: None,
+            bleed: 3.,
+            crop_marks: true,
+            crop_length: 5.,
+            crop_offset: 2.,
+            crosshairs: true,
+            crosshair_size: 4.,
+            bleed_box: false,
+        }
+    }
+}
@@ -4,18 +4,27 @@ use std::path::PathBuf;
 use crate::{
     core::{
         config::AppConfig,
+        estimate::PlotEstimate,
         machine::MachineConfig,
-        project::{Paper, PenDetail},
+        post::PostSummary,
+        project::{Paper, PenDetail, PenPostSettings},
+        resume::ResumeFrom,
         sender::{PlotterResponse, PlotterState},
+        validate::Violation,
     },
     view_model::view_model_patch::ViewModelPatch,
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 pub mod hatch;
+pub mod marks;
 pub mod mat;
+pub mod tile;
 pub use hatch::*;
+pub use marks::*;
 pub use mat::*;
+pub use tile::*;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Default, Clone)]
 pub enum SelectionType {
@@ -32,6 +41,14 @@ pub enum ViewCommand {
     ReorderToDestination(usize),
     OrderByPenId,
     SetGCode(String),
+    ExportGCode {
+        path: PathBuf,
+        per_pen: bool,
+    },
+    ExportTiles {
+        path: PathBuf,
+        config: TileConfig,
+    },
     RequestSourceImage {
         zoom: f64,
         rotation: Option<((f64, f64), f64)>,
@@ -63,8 +80,10 @@ pub enum ViewCommand {
     Scale(f64),
     // Scale so the whole drawing fits with AT LEAST this margin on printable area/paper
     ScaleMatTo(MatTarget),
+    SetMarks(Option<MarksConfig>),
     Post,
     StartPlot,
+    ResumePlot(ResumeFrom),
     PausePlot,
     CancelPlot,
     PenUp,
@@ -74,6 +93,7 @@ pub enum ViewCommand {
     DisconnectPlotter,
     Quit,
     ApplyPens(Vec<PenDetail>),
+    ApplyPenPostSettings(Uuid, PenPostSettings),
     ApplyPenToSelection(usize), // Tool ID.
     Undo,
     ResetProject,
@@ -101,6 +121,10 @@ pub enum ViewCommand {
         id: usize,
         name: String,
     },
+    PinLayer {
+        id: usize,
+        pinned: bool,
+    },
     PrepHatch(Option<HatchConfig>),
     None,
 }
@@ -139,6 +163,9 @@ pub enum ApplicationStateChangeMsg {
     PlotterResponse(PlotterResponse),
     FoundPorts(Vec<String>),
     PostComplete(usize),
+    PostSummary(PostSummary),
+    PlotEstimate(PlotEstimate),
+    PostViolations(Vec<Violation>),
     Error(String),
     UndoAvailable(bool),
     PaperChanged(Paper),
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// What each tile is sized to.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub enum TileTarget {
+    #[default]
+    Machine,
+    Paper,
+}
+
+impl TileTarget {
+    pub fn all() -> Vec<TileTarget> {
+        vec![TileTarget::Machine, TileTarget::Paper]
+    }
+}
+
+impl Display for TileTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TileTarget::Machine => write!(f, "Machine"),
+            TileTarget::Paper => write!(f, "Paper"),
+        }
+    }
+}
+
+/// How to split a drawing that's bigger than the machine across sheets.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct TileConfig {
+    pub target: TileTarget,
+    /// How far (mm) neighbouring tiles overlap.
+    pub overlap: f64,
+    /// Crosses near each tile's corners, which land on the neighbouring
+    /// tile's crosses once the sheets are lined up.
+    pub registration_marks: bool,
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        Self {
+            target: TileTarget::default(),
+            overlap: 10.,
+            registration_marks: true,
+        }
+    }
+}
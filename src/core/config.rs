@@ -47,15 +47,112 @@ pub struct ImportOptions {
     pub generate_pens_from_svg: bool,
 }
 
+/// How we order the lines within a geometry before posting them.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug, Default)]
+pub enum PathStrategy {
+    /// The plotty optimizer's greedy pass (the historical behavior).
+    #[default]
+    Greedy,
+    /// Nearest next line, flipping lines when their far end is closer.
+    NearestNeighbour,
+    /// Nearest neighbour, then 2-opt improvement until the time budget runs out.
+    TwoOpt,
+}
+
+impl PathStrategy {
+    pub fn all() -> Vec<PathStrategy> {
+        vec![
+            PathStrategy::Greedy,
+            PathStrategy::NearestNeighbour,
+            PathStrategy::TwoOpt,
+        ]
+    }
+}
+
+impl std::fmt::Display for PathStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathStrategy::Greedy => write!(f, "Greedy"),
+            PathStrategy::NearestNeighbour => write!(f, "Nearest neighbour"),
+            PathStrategy::TwoOpt => write!(f, "2-opt"),
+        }
+    }
+}
+
+/// How we thin out overly dense polylines before posting them.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug, Default)]
+pub enum SimplifyMethod {
+    #[default]
+    None,
+    DouglasPeucker,
+    Visvalingam,
+}
+
+impl SimplifyMethod {
+    pub fn all() -> Vec<SimplifyMethod> {
+        vec![
+            SimplifyMethod::None,
+            SimplifyMethod::DouglasPeucker,
+            SimplifyMethod::Visvalingam,
+        ]
+    }
+}
+
+impl std::fmt::Display for SimplifyMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimplifyMethod::None => write!(f, "None"),
+            SimplifyMethod::DouglasPeucker => write!(f, "Douglas-Peucker"),
+            SimplifyMethod::Visvalingam => write!(f, "Visvalingam-Whyatt"),
+        }
+    }
+}
+
+fn default_simplify_tolerance() -> f64 {
+    0.05
+}
+
+fn default_two_opt_budget_ms() -> u64 {
+    2000
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct PostOptions {
     pub reorder_by_tool: bool,
+    /// Optimize travel across all the consecutive geometry for a pen,
+    /// instead of one geometry at a time.
+    #[serde(default)]
+    pub pool_by_pen: bool,
+    #[serde(default)]
+    pub path_strategy: PathStrategy,
+    /// How long the 2-opt pass may spend on the whole post, in milliseconds.
+    #[serde(default = "default_two_opt_budget_ms")]
+    pub two_opt_budget_ms: u64,
+    /// Fit arcs to curves within this tolerance (mm), or None to post
+    /// everything as straight lines.
+    #[serde(default)]
+    pub arc_tolerance: Option<f64>,
+    #[serde(default)]
+    pub simplify: SimplifyMethod,
+    /// Default simplification tolerance (mm), unless a pen overrides it.
+    #[serde(default = "default_simplify_tolerance")]
+    pub simplify_tolerance: f64,
+    /// Refuse to post while geometry is off the paper or machine.
+    #[serde(default)]
+    pub refuse_invalid: bool,
 }
 
 impl Default for PostOptions {
     fn default() -> Self {
         Self {
             reorder_by_tool: true,
+            pool_by_pen: false,
+            path_strategy: PathStrategy::default(),
+            two_opt_budget_ms: default_two_opt_budget_ms(),
+            arc_tolerance: None,
+            simplify: SimplifyMethod::default(),
+            simplify_tolerance: default_simplify_tolerance(),
+            refuse_invalid: false,
         }
     }
 }
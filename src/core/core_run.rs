@@ -3,6 +3,8 @@ use std::collections::BTreeSet;
 use std::time::{Duration, Instant};
 
 use super::ApplicationCore;
+use super::export::export_program;
+use super::marks::{regenerate_marks, remove_marks};
 use super::project::Project;
 use super::sender::{PlotterCommand, PlotterState};
 use super::serial;
@@ -115,6 +117,9 @@ impl ApplicationCore {
                         ViewCommand::StartPlot => {
                             self.yolo_send_plotter_cmd(PlotterCommand::Run);
                         }
+                        ViewCommand::ResumePlot(from) => {
+                            self.handle_resume(&from);
+                        }
                         ViewCommand::PausePlot => {
                             self.yolo_send_plotter_cmd(PlotterCommand::Stop);
                         }
@@ -128,11 +133,13 @@ impl ApplicationCore {
                         }
                         ViewCommand::UpdateMachineConfig(machine_config) => {
                             self.project.set_machine(Some(machine_config));
+                            self.sync_plotter_variant();
                         }
                         ViewCommand::SendCommand(cmd) => {
                             self.yolo_send_plotter_cmd(PlotterCommand::Command(cmd));
                         }
                         ViewCommand::ConnectPlotter(port_path) => {
+                            self.sync_plotter_variant();
                             self.yolo_send_plotter_cmd(PlotterCommand::Connect(port_path))
                         }
                         ViewCommand::DisconnectPlotter => {
@@ -163,7 +170,8 @@ impl ApplicationCore {
                                 &self.project,
                                 // gcode,
                                 extents,
-                                (self.progress.0, self.progress.1),
+                                self.progress,
+                                self.pen_position,
                                 resolution,
                                 &self.state_change_out,
                                 &self.cancel_render,
@@ -195,6 +203,10 @@ impl ApplicationCore {
                             self.checkpoint();
                             self.project.update_pen_details(&pen_details);
                         }
+                        ViewCommand::ApplyPenPostSettings(pen_uuid, settings) => {
+                            self.checkpoint();
+                            self.project.pen_post.insert(pen_uuid, settings);
+                        }
                         ViewCommand::Undo => self.undo(),
                         ViewCommand::SetPaper(paper) => {
                             self.checkpoint();
@@ -376,6 +388,11 @@ impl ApplicationCore {
                                 self.project.plot_geometry[id].name = name;
                             }
                         }
+                        ViewCommand::PinLayer { id, pinned } => {
+                            if id < self.project.plot_geometry.len() {
+                                self.project.plot_geometry[id].pinned = pinned;
+                            }
+                        }
                         ViewCommand::Translate(x, y) => {
                             self.checkpoint();
                             self.project.translate_geometry_mut((x, y), &self.picked);
@@ -410,6 +427,7 @@ impl ApplicationCore {
                                     ..Default::default()
                                 }))
                                 .expect("Failed to send error to viewmodel.");
+                            self.sync_plotter_variant();
                         }
                         ViewCommand::SaveMachineConfig(path_buf) => {
                             self.project.save_machine(&path_buf).unwrap_or_else(|err| {
@@ -424,6 +442,37 @@ impl ApplicationCore {
                                 ))
                             });
                         }
+                        ViewCommand::ExportGCode { path, per_pen } => {
+                            let program = self.program.clone().unwrap_or_default();
+                            match export_program(
+                                &path,
+                                &program,
+                                self.post_summary.as_ref(),
+                                per_pen,
+                            ) {
+                                Ok(written) => self.yolo_app_state_change(
+                                    ApplicationStateChangeMsg::ProgressMessage {
+                                        message: format!(
+                                            "Exported {} line(s) to {} file(s)",
+                                            program.len(),
+                                            written.len()
+                                        ),
+                                        percentage: 100,
+                                    },
+                                ),
+                                Err(err) => self.yolo_app_state_change(
+                                    ApplicationStateChangeMsg::Error(format!(
+                                        "Failed to export G-code to {}! Err:{}",
+                                        path.as_os_str().to_string_lossy(),
+                                        err
+                                    )),
+                                ),
+                            }
+                            self.ctx.request_repaint();
+                        }
+                        ViewCommand::ExportTiles { path, config } => {
+                            self.handle_export_tiles(&path, &config);
+                        }
                         ViewCommand::SetGCode(gcode) => {
                             let program: Vec<String> =
                                 gcode.split("\n").map(|line| line.to_string()).collect();
@@ -432,6 +481,10 @@ impl ApplicationCore {
                         ViewCommand::ScaleMatTo(mat_target) => {
                             // println!("Received Mat To Target {}", &mat_target);
                             self.checkpoint();
+                            // The marks get drawn around the matted artwork
+                            // afterwards, rather than being matted with it.
+                            remove_marks(&mut self.project);
+                            self.project.regenerate_extents();
                             self.project
                                 .mat_to_target(mat_target)
                                 .unwrap_or_else(|err| {
@@ -443,6 +496,11 @@ impl ApplicationCore {
                                         .to_string(),
                                     ))
                                 });
+                            regenerate_marks(&mut self.project).unwrap_or_else(|err| {
+                                self.yolo_app_state_change(ApplicationStateChangeMsg::Error(
+                                    format!("Failed to regenerate marks: {}", err).to_string(),
+                                ))
+                            });
                             self.rebuild_after_content_change();
                             self.state_change_out
                                 .send(ApplicationStateChangeMsg::PatchViewModel(
@@ -450,6 +508,10 @@ impl ApplicationCore {
                                 ))
                                 .expect("Failed to send patch to viewmodel.");
                         }
+                        ViewCommand::SetMarks(marks) => {
+                            self.checkpoint();
+                            self.handle_set_marks(marks);
+                        }
                         ViewCommand::GroupAllByTool => {
                             self.checkpoint();
                             for tool in self.project.pens.clone() {
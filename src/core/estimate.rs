@@ -0,0 +1,507 @@
+use geo::Coord;
+use uuid::Uuid;
+
+use super::machine::{MachineConfig, MachineVariant};
+use super::modal::parse_words;
+use super::post::arc_length;
+
+/// What one pen's share of the job will cost.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PenEstimate {
+    /// The pen's name, for display.
+    pub pen: String,
+    /// Which pen this is, or None for the setup and total rows.
+    pub pen_uuid: Option<Uuid>,
+    /// Pen-down distance in mm.
+    pub draw_distance: f64,
+    /// Pen-up distance in mm.
+    pub travel_distance: f64,
+    pub lifts: usize,
+    pub seconds: f64,
+}
+
+impl PenEstimate {
+    fn new(pen: &str, pen_uuid: Option<Uuid>) -> Self {
+        Self {
+            pen: pen.to_string(),
+            pen_uuid,
+            ..Default::default()
+        }
+    }
+}
+
+/// How long a posted program should take, broken down by pen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlotEstimate {
+    pub pens: Vec<PenEstimate>,
+}
+
+impl PlotEstimate {
+    pub fn total(&self) -> PenEstimate {
+        self.pens
+            .iter()
+            .fold(PenEstimate::new("Total", None), |mut total, pen| {
+                total.draw_distance += pen.draw_distance;
+                total.travel_distance += pen.travel_distance;
+                total.lifts += pen.lifts;
+                total.seconds += pen.seconds;
+                total
+            })
+    }
+}
+
+/// Formats seconds as something like "1h 23m" or "4m 05s".
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, (seconds % 3600) / 60)
+    } else if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+impl std::fmt::Display for PlotEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.total();
+        write!(
+            f,
+            "Estimated plot time {}: {:.1}m drawn, {:.1}m travel, {} pen lifts",
+            format_duration(total.seconds),
+            total.draw_distance / 1000.,
+            total.travel_distance / 1000.,
+            total.lifts
+        )?;
+        for pen in &self.pens {
+            write!(
+                f,
+                "\n  {}: {}, {:.0}mm drawn, {:.0}mm travel, {} lifts",
+                pen.pen,
+                format_duration(pen.seconds),
+                pen.draw_distance,
+                pen.travel_distance,
+                pen.lifts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How far GRBL lets the path cut a corner so the pen can keep moving
+/// through it, in mm (GRBL's default $11).
+const JUNCTION_DEVIATION: f64 = 0.01;
+
+/// The acceleration along a move, limited by whichever axis runs out
+/// first.
+fn move_accel(distance: f64, delta: (f64, f64), acceleration: (f64, f64)) -> f64 {
+    let mut accel = f64::INFINITY;
+    for (axis_accel, component) in [
+        (acceleration.0, delta.0.abs() / distance),
+        (acceleration.1, delta.1.abs() / distance),
+    ] {
+        if component > 1e-9 {
+            accel = accel.min(axis_accel / component);
+        }
+    }
+    if !accel.is_finite() || accel <= 0. {
+        accel = acceleration.0.min(acceleration.1).max(1.);
+    }
+    accel
+}
+
+/// Time for a move that enters at one speed and leaves at another (in
+/// mm/s), using a trapezoidal velocity profile.
+fn profile_time(distance: f64, velocity: f64, accel: f64, entry: f64, exit: f64) -> f64 {
+    let (entry, exit) = (entry.min(velocity), exit.min(velocity));
+    let ramp_up = (velocity * velocity - entry * entry) / (2. * accel);
+    let ramp_down = (velocity * velocity - exit * exit) / (2. * accel);
+    if ramp_up + ramp_down <= distance {
+        return (velocity - entry) / accel
+            + (velocity - exit) / accel
+            + (distance - ramp_up - ramp_down) / velocity;
+    }
+    // Too short to reach speed, so it peaks part way.
+    let peak = ((2. * accel * distance + entry * entry + exit * exit) / 2.).sqrt();
+    (peak - entry) / accel + (peak - exit) / accel
+}
+
+/// Time for a single move that starts and ends at rest.
+fn move_time(distance: f64, delta: (f64, f64), feedrate: f64, acceleration: (f64, f64)) -> f64 {
+    if distance <= 0. || feedrate <= 0. {
+        return 0.;
+    }
+    let accel = move_accel(distance, delta, acceleration);
+    profile_time(distance, feedrate / 60., accel, 0., 0.)
+}
+
+/// How fast GRBL takes the corner between two directions (unit vectors),
+/// by its junction deviation rule.
+fn junction_speed(from: (f64, f64), to: (f64, f64), accel: f64, velocity: f64) -> f64 {
+    // The cosine of the angle between where we came from and where we're
+    // going, so -1 is straight on.
+    let cos = -(from.0 * to.0 + from.1 * to.1);
+    if cos <= -0.999999 {
+        return velocity;
+    }
+    if cos >= 0.999999 {
+        return 0.;
+    }
+    let sin_half = ((1. - cos) / 2.).sqrt();
+    (accel * JUNCTION_DEVIATION * sin_half / (1. - sin_half))
+        .sqrt()
+        .min(velocity)
+}
+
+/// A draw move, kept until the pen next stops so the whole run can be
+/// planned at once.
+struct Draw {
+    distance: f64,
+    velocity: f64,
+    accel: f64,
+    /// Direction of travel at the start and end, as unit vectors.
+    start: (f64, f64),
+    end: (f64, f64),
+}
+
+/// Times a run of draws that starts and ends at rest, without stopping at
+/// the corners in between. Like GRBL's planner, each corner is taken as
+/// fast as its angle allows, as long as there's room to slow down for the
+/// next one and room to get up to speed from the last.
+fn plan_time(draws: &[Draw]) -> f64 {
+    let draws: Vec<&Draw> = draws
+        .iter()
+        .filter(|draw| draw.distance > 0. && draw.velocity > 0.)
+        .collect();
+    // The speed at the end of each draw.
+    let mut exits: Vec<f64> = draws
+        .windows(2)
+        .map(|pair| {
+            junction_speed(
+                pair[0].end,
+                pair[1].start,
+                pair[0].accel.min(pair[1].accel),
+                pair[0].velocity.min(pair[1].velocity),
+            )
+        })
+        .chain([0.])
+        .collect();
+    for idx in (0..draws.len().saturating_sub(1)).rev() {
+        let next = draws[idx + 1];
+        let slowing = (exits[idx + 1].powi(2) + 2. * next.accel * next.distance).sqrt();
+        exits[idx] = exits[idx].min(slowing);
+    }
+    let mut entry = 0.;
+    let mut seconds = 0.;
+    for (draw, exit) in draws.iter().zip(exits.iter_mut()) {
+        let speeding = (entry * entry + 2. * draw.accel * draw.distance).sqrt();
+        *exit = exit.min(speeding);
+        seconds += profile_time(draw.distance, draw.velocity, draw.accel, entry, *exit);
+        entry = *exit;
+    }
+    seconds
+}
+
+fn unit(x: f64, y: f64) -> (f64, f64) {
+    let length = (x * x + y * y).sqrt();
+    if length > 0. {
+        (x / length, y / length)
+    } else {
+        (0., 0.)
+    }
+}
+
+/// Walks a posted program and estimates draw/travel distance, pen lifts
+/// and time for each pen. Pen starts are the (line, uuid, name) the post
+/// recorded; anything before the first one is the machine's setup. Lifts
+/// are the lines the post lifted the pen at. Rapids between a draw and the
+/// next lift are keepdowns, so they're drawn rather than travelled.
+pub fn estimate_program(
+    program: &[String],
+    machine: &MachineConfig,
+    pen_starts: &[(usize, Uuid, String)],
+    lifts: &[usize],
+) -> PlotEstimate {
+    let mut estimate = PlotEstimate {
+        pens: vec![PenEstimate::new("Setup", None)],
+    };
+    let mut starts = pen_starts.iter().peekable();
+    let mut lifts = lifts.iter().peekable();
+    let acceleration = machine.acceleration();
+    let is_hpgl = machine.variant() == MachineVariant::HPGL;
+    let mut position = Coord { x: 0., y: 0. };
+    let mut motion = 0u32;
+    let mut feedrate = machine.feedrate();
+    let mut drawing = false;
+    // Consecutive draws don't stop between moves, so they're timed together
+    // once the pen stops.
+    let mut pending: Vec<Draw> = Vec::new();
+
+    for (idx, line) in program.iter().enumerate() {
+        // A pen's last lift is on its way to the next pen, so it's counted
+        // before the pen changes.
+        while lifts.next_if(|lift| **lift <= idx).is_some() {
+            if drawing {
+                estimate
+                    .pens
+                    .last_mut()
+                    .expect("Always at least one pen")
+                    .lifts += 1;
+            }
+            drawing = false;
+        }
+        while let Some((_, uuid, name)) = starts.next_if(|(start, _, _)| *start <= idx) {
+            estimate
+                .pens
+                .last_mut()
+                .expect("Always at least one pen")
+                .seconds += plan_time(&pending);
+            pending.clear();
+            estimate.pens.push(PenEstimate::new(name, Some(*uuid)));
+        }
+        let pen = estimate.pens.last_mut().expect("Always at least one pen");
+        let trimmed = line.trim();
+
+        if is_hpgl {
+            if let Some(velocity) = trimmed
+                .strip_prefix("VS")
+                .and_then(|v| v.trim_end_matches(';').parse::<f64>().ok())
+            {
+                // VS is in cm/s.
+                feedrate = velocity * 600.;
+            }
+            for (pen_down, x, y) in super::hpgl::parse_hpgl_moves(trimmed) {
+                let end = Coord { x, y };
+                let delta = (end.x - position.x, end.y - position.y);
+                let distance = (delta.0 * delta.0 + delta.1 * delta.1).sqrt();
+                if pen_down {
+                    pen.draw_distance += distance;
+                    pen.seconds += move_time(distance, delta, feedrate, acceleration);
+                } else {
+                    if drawing {
+                        pen.lifts += 1;
+                    }
+                    pen.travel_distance += distance;
+                    pen.seconds +=
+                        move_time(distance, delta, machine.rapid_feedrate(), acceleration);
+                }
+                drawing = pen_down;
+                position = end;
+            }
+            continue;
+        }
+
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        // Anything but another draw brings the pen to a stop.
+        let mut stop = |pen: &mut PenEstimate| {
+            pen.seconds += plan_time(&pending);
+            pending.clear();
+        };
+        if trimmed.starts_with('$') {
+            stop(pen);
+            continue;
+        }
+        let words = parse_words(trimmed);
+        let word = |letter: char| {
+            words
+                .iter()
+                .find(|(l, value)| *l == letter && !value.is_nan())
+                .map(|(_, value)| *value)
+        };
+        let mut is_motion = words.iter().all(|(letter, _)| *letter != 'G');
+        for (letter, value) in &words {
+            if *letter != 'G' {
+                continue;
+            }
+            match *value as u32 {
+                g @ 0..=3 => {
+                    motion = g;
+                    is_motion = true;
+                }
+                // Marlin style, P in milliseconds or S in seconds.
+                4 => {
+                    pen.seconds += word('P').map(|ms| ms / 1000.).or(word('S')).unwrap_or(0.);
+                }
+                28 => position = Coord { x: 0., y: 0. },
+                92 => {
+                    position = Coord {
+                        x: word('X').unwrap_or(position.x),
+                        y: word('Y').unwrap_or(position.y),
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let Some(f) = word('F') {
+            feedrate = f;
+        }
+        if !is_motion || (word('X').is_none() && word('Y').is_none()) {
+            stop(pen);
+            continue;
+        }
+        let end = Coord {
+            x: word('X').unwrap_or(position.x),
+            y: word('Y').unwrap_or(position.y),
+        };
+        let delta = (end.x - position.x, end.y - position.y);
+        let chord = (delta.0 * delta.0 + delta.1 * delta.1).sqrt();
+        match motion {
+            0 => {
+                stop(pen);
+                if drawing {
+                    pen.draw_distance += chord;
+                } else {
+                    pen.travel_distance += chord;
+                }
+                pen.seconds += move_time(chord, delta, machine.rapid_feedrate(), acceleration);
+            }
+            _ => {
+                drawing = true;
+                let (distance, start, finish) = if motion == 1 {
+                    let direction = unit(delta.0, delta.1);
+                    (chord, direction, direction)
+                } else {
+                    let center = Coord {
+                        x: position.x + word('I').unwrap_or(0.),
+                        y: position.y + word('J').unwrap_or(0.),
+                    };
+                    // Arcs head along the tangent, which is square to the
+                    // radius.
+                    let tangent = |point: &Coord<f64>| match motion == 2 {
+                        true => unit(point.y - center.y, center.x - point.x),
+                        false => unit(center.y - point.y, point.x - center.x),
+                    };
+                    (
+                        arc_length(&position, &end, &center, motion == 2),
+                        tangent(&position),
+                        tangent(&end),
+                    )
+                };
+                // Arcs use the slower axis all the way round.
+                let delta = if motion == 1 {
+                    delta
+                } else {
+                    (distance, distance)
+                };
+                pen.draw_distance += distance;
+                pending.push(Draw {
+                    distance,
+                    velocity: feedrate / 60.,
+                    accel: move_accel(distance, delta, acceleration),
+                    start,
+                    end: finish,
+                });
+            }
+        }
+        position = end;
+    }
+    estimate
+        .pens
+        .last_mut()
+        .expect("Always at least one pen")
+        .seconds += plan_time(&pending);
+    estimate
+        .pens
+        .retain(|pen| pen.pen_uuid.is_some() || pen.seconds > 0.);
+    estimate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_time() {
+        // 10mm at 600mm/min (10mm/s) with 100mm/s² accel: 1mm to ramp up
+        // and down, so 1s at speed plus 0.1s lost to the ramps.
+        let t = move_time(10., (10., 0.), 600., (100., 100.));
+        assert!((t - 1.1).abs() < 1e-9);
+        // Too short to reach speed at all.
+        let t = move_time(0.5, (0., 0.5), 600., (100., 100.));
+        assert!((t - 2. * (0.005f64).sqrt()).abs() < 1e-9);
+        // Diagonals are limited by the slower axis.
+        let fast = move_time(10., (10., 0.), 600., (100., 10.));
+        let slow = move_time(10., (0., 10.), 600., (100., 10.));
+        assert!(slow > fast);
+    }
+
+    #[test]
+    fn test_junctions() {
+        let machine = MachineConfig::default();
+        let seconds = |points: &[(f64, f64)]| {
+            let program: Vec<String> = points
+                .iter()
+                .map(|(x, y)| format!("G1 F3000 X{} Y{}", x, y))
+                .collect();
+            estimate_program(&program, &machine, &[], &[])
+                .total()
+                .seconds
+        };
+        // 100 1mm steps along a line run straight through at 50mm/s, where
+        // stopping at each one would take three times as long.
+        let straight: Vec<(f64, f64)> = (1..=100).map(|x| (x as f64, 0.)).collect();
+        let acceleration = machine.acceleration();
+        let stopping = 100. * move_time(1., (1., 0.), 3000., acceleration);
+        let profile = move_time(100., (100., 0.), 3000., acceleration);
+        assert!((seconds(&straight) - profile).abs() < 1e-6);
+        assert!(stopping > 3. * profile);
+        // Square corners slow down a lot, but don't stop.
+        let zigzag: Vec<(f64, f64)> = (1..=100)
+            .map(|step| ((step / 2) as f64, ((step + 1) / 2) as f64))
+            .collect();
+        assert!(seconds(&zigzag) > seconds(&straight));
+        assert!(seconds(&zigzag) < stopping);
+    }
+
+    #[test]
+    fn test_estimate_program() {
+        let program: Vec<String> = vec![
+            "G4 P500",
+            "G0 X10 Y0",
+            "M280 S13",
+            "G4 P1000",
+            "G01 F600 X20 Y0",
+            "Y10",
+            // A keepdown.
+            "G0 X20 Y12",
+            "G1 X20 Y20",
+            "M280 S30",
+            "G0 X0 Y0",
+            "$M06 T2",
+            "G0 X10 Y0",
+            "G1 X10 Y10",
+            "M280 S30",
+            "G0 X0 Y0",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+        let machine = MachineConfig::default();
+        let estimate = estimate_program(
+            &program,
+            &machine,
+            &[
+                (2, Uuid::new_v4(), "Black".to_string()),
+                (10, Uuid::new_v4(), "Red".to_string()),
+            ],
+            &[8, 13],
+        );
+        assert_eq!(estimate.pens.len(), 3);
+        assert_eq!(estimate.pens[0].pen, "Setup");
+        assert_eq!(estimate.pens[0].travel_distance, 10.);
+        let black = &estimate.pens[1];
+        assert_eq!(black.pen, "Black");
+        assert_eq!(black.draw_distance, 30.);
+        assert_eq!(black.travel_distance, 800f64.sqrt());
+        assert_eq!(black.lifts, 1);
+        assert!(black.seconds > 3.);
+        let red = &estimate.pens[2];
+        assert_eq!(red.draw_distance, 10.);
+        assert_eq!(red.lifts, 1);
+        assert_eq!(estimate.total().draw_distance, 40.);
+        assert_eq!(format_duration(3725.), "1h 02m");
+        assert_eq!(format_duration(65.), "1m 05s");
+    }
+}
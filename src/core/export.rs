@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+
+use super::post::PostSummary;
+
+/// Splits a posted program at each pen change, giving every piece its own
+/// copy of the prelude and epilog. Returns (pen name, program) pairs, one
+/// per pen change, so two pens sharing a name still get their own pieces.
+pub fn split_by_pen(program: &[String], summary: &PostSummary) -> Vec<(String, Vec<String>)> {
+    let Some((first, _, _)) = summary.pen_starts.first() else {
+        return vec![("program".to_string(), program.to_vec())];
+    };
+    let epilog_start = summary.epilog_start.clamp(*first, program.len());
+    let prelude = &program[..*first];
+    let epilog = &program[epilog_start..];
+    summary
+        .pen_starts
+        .iter()
+        .enumerate()
+        .map(|(idx, (start, _, name))| {
+            let end = summary
+                .pen_starts
+                .get(idx + 1)
+                .map(|(next, _, _)| *next)
+                .unwrap_or(epilog_start)
+                .min(epilog_start);
+            let mut piece = prelude.to_vec();
+            piece.extend_from_slice(&program[(*start).min(end)..end]);
+            piece.extend_from_slice(epilog);
+            (name.clone(), piece)
+        })
+        .collect()
+}
+
+/// Something safe to put in a filename.
+fn slug(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if slug.is_empty() {
+        "pen".to_string()
+    } else {
+        slug
+    }
+}
+
+fn write_program(path: &Path, program: &[String]) -> AnyResult<()> {
+    let mut file = File::create(path)?;
+    for line in program {
+        writeln!(file, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Writes the program to path. With per_pen it's split by pen instead,
+/// into files named like `drawing-01-Black_Pen.gcode` next to path.
+pub fn export_program(
+    path: &Path,
+    program: &[String],
+    summary: Option<&PostSummary>,
+    per_pen: bool,
+) -> AnyResult<Vec<PathBuf>> {
+    if !per_pen {
+        write_program(path, program)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let summary = summary.ok_or(anyhow!(
+        "Per-pen export needs a fresh post, not an edited program"
+    ))?;
+    export_pieces(path, &split_by_pen(program, summary))
+}
+
+/// Writes each named piece to its own file next to path, numbered in order
+/// like `drawing-01-Black_Pen.gcode`.
+pub fn export_pieces(path: &Path, pieces: &[(String, Vec<String>)]) -> AnyResult<Vec<PathBuf>> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or("program".to_string());
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or("gcode".to_string());
+    let mut written = Vec::new();
+    for (idx, (name, piece)) in pieces.iter().enumerate() {
+        let piece_path = path.with_file_name(format!(
+            "{}-{:02}-{}.{}",
+            stem,
+            idx + 1,
+            slug(name),
+            extension
+        ));
+        write_program(&piece_path, piece)?;
+        written.push(piece_path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_split_by_pen() {
+        let program: Vec<String> = ["HOME", "T1", "BLACK", "T2", "RED", "FINISHED"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let summary = PostSummary {
+            pen_starts: vec![
+                (1, Uuid::new_v4(), "Black Pen".to_string()),
+                (3, Uuid::new_v4(), "Black Pen".to_string()),
+            ],
+            epilog_start: 5,
+            ..Default::default()
+        };
+        let pieces = split_by_pen(&program, &summary);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].0, "Black Pen");
+        assert_eq!(pieces[0].1, vec!["HOME", "T1", "BLACK", "FINISHED"]);
+        // Same name, different pen, so it's still split.
+        assert_eq!(pieces[1].1, vec!["HOME", "T2", "RED", "FINISHED"]);
+        assert_eq!(slug("Black Pen/0.5"), "Black_Pen_0_5");
+    }
+}
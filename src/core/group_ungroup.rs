@@ -46,6 +46,8 @@ impl ApplicationCore {
                                 }
                             },
                             keepdown_strategy: geo.keepdown_strategy,
+                            pinned: geo.pinned && idx == 0,
+                            marks: geo.marks,
                         })
                     }
                 } else {
@@ -98,6 +100,8 @@ impl ApplicationCore {
                 pen_uuid: tmp_geo.pen_uuid,
                 geometry: GeometryKind::Stroke(Geometry::MultiLineString(new_mls)),
                 keepdown_strategy: tmp_geo.keepdown_strategy,
+                pinned: tmp_geo.pinned,
+                marks: false,
             });
 
             self.state_change_out
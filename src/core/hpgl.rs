@@ -0,0 +1,219 @@
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+use geo::{Coord, LineString};
+
+use super::config::PostOptions;
+use super::optimize::PathPlanner;
+use super::post::{PostSummary, offset_for_tool, pen_runs, post_transform, simplify_run};
+use super::project::{PenDetail, Project};
+
+/// HPGL plotter units are 0.025mm, so there are 40 of them per mm.
+pub const PLOTTER_UNITS_PER_MM: f64 = 40.;
+/// Old plotters have tiny input buffers, so we split long PD runs up.
+const MAX_POINTS_PER_PD: usize = 32;
+
+/// Converts millimeters into integer HPGL plotter units.
+pub fn mm_to_plotter_units(mm: f64) -> i64 {
+    (mm * PLOTTER_UNITS_PER_MM).round() as i64
+}
+
+/// Our feedrates are in mm/min, but HPGL VS wants cm/s.
+fn feedrate_to_velocity(feedrate: f64) -> u32 {
+    (feedrate / 600.).round().max(1.) as u32
+}
+
+fn coord_to_pu(coord: &Coord<f64>) -> (i64, i64) {
+    (mm_to_plotter_units(coord.x), mm_to_plotter_units(coord.y))
+}
+
+/// Turns a single linestring into a PU to the start, followed by as many
+/// PD instructions as it takes to draw the rest of it.
+fn linestring_to_hpgl(line: &LineString<f64>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    if let Some(start) = line.0.first() {
+        let mut last = coord_to_pu(start);
+        out.push(format!("PU{},{};", last.0, last.1));
+        let mut points: Vec<(i64, i64)> = Vec::new();
+        for coord in line.0.iter().skip(1) {
+            let point = coord_to_pu(coord);
+            if point != last {
+                points.push(point);
+                last = point;
+            }
+        }
+        for chunk in points.chunks(MAX_POINTS_PER_PD) {
+            out.push(format!(
+                "PD{};",
+                chunk
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, y))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ));
+        }
+    }
+    out
+}
+
+/// Posts the project as HPGL instead of running it through the
+/// Tera templates. Pens are selected by their tool_id.
+pub fn post_hpgl(
+    project: &Project,
+    options: &PostOptions,
+) -> AnyResult<(Vec<String>, PostSummary)> {
+    let machine = project.machine().ok_or(anyhow!("Invalid machine"))?;
+    let tx_affine2 = post_transform(project)?;
+    let to_bed = machine.bed_to_machine().inverse();
+    let mut program: Vec<String> = vec!["IN;".to_string(), "PA;".to_string()];
+    let mut last_tool: usize = usize::MAX;
+    let mut last_velocity: Option<u32> = None;
+    let mut planner = PathPlanner::new(options, machine.keepdown().unwrap_or(1.0));
+    let mut summary = PostSummary::default();
+
+    for run in pen_runs(project, &tx_affine2, options) {
+        let pen = project
+            .pen_by_uuid(run.pen_uuid)
+            .unwrap_or(PenDetail::default());
+        let pen_settings = project.pen_post_settings(run.pen_uuid);
+        let geo_lines = planner.plan(
+            &pen_settings.orient(
+                &simplify_run(project, &run, &pen, options, &mut summary),
+                &to_bed,
+            ),
+            pen_settings.reversible(),
+        );
+        let geo_lines = offset_for_tool(&geo_lines, &machine, pen.tool_id);
+        summary.record_pen(program.len(), &pen);
+
+        if pen.tool_id != last_tool {
+            last_tool = pen.tool_id;
+            program.push("PU;".to_string());
+            summary
+                .tool_changes
+                .push((program.len(), program.len() + 1));
+            program.push(format!("SP{};", pen.tool_id));
+        }
+        let velocity = feedrate_to_velocity(pen.feed_rate.unwrap_or(machine.feedrate()));
+        if last_velocity != Some(velocity) {
+            program.push(format!("VS{};", velocity));
+            last_velocity = Some(velocity);
+        }
+        for idx in &run.geometries {
+            summary.geometry_starts.push((program.len(), *idx));
+        }
+        for line in geo_lines.0.iter() {
+            for pass in pen_settings.pass_lines(line) {
+                // Every line starts with a PU to its first point.
+                summary.stroke_starts.push(program.len());
+                program.extend(linestring_to_hpgl(&pass));
+            }
+        }
+    }
+    summary.epilog_start = program.len();
+    program.push("PU;".to_string());
+    program.push("SP0;".to_string());
+    summary.record_planner(&options.path_strategy, &planner);
+    Ok((program, summary))
+}
+
+/// Parses the PU/PD/PA moves out of a line of HPGL, returning
+/// (pen_down, x_mm, y_mm) for each absolute position visited.
+/// Everything else is ignored, which is fine for previews.
+pub fn parse_hpgl_moves(line: &str) -> Vec<(bool, f64, f64)> {
+    let mut moves = Vec::new();
+    let mut pen_down = false;
+    for instruction in line.split(';') {
+        let instruction = instruction.trim();
+        if instruction.len() < 2 || !instruction.is_char_boundary(2) {
+            continue;
+        }
+        let (mnemonic, args) = instruction.split_at(2);
+        match mnemonic.to_uppercase().as_str() {
+            "PU" => pen_down = false,
+            "PD" => pen_down = true,
+            "PA" => (),
+            _ => continue,
+        }
+        let values: Vec<f64> = args
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .filter_map(|v| v.parse::<f64>().ok())
+            .collect();
+        for pair in values.chunks_exact(2) {
+            moves.push((
+                pen_down,
+                pair[0] / PLOTTER_UNITS_PER_MM,
+                pair[1] / PLOTTER_UNITS_PER_MM,
+            ));
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::machine::{MachineConfig, MachineVariant};
+    use crate::core::project::{BAPGeometry, GeometryKind, KeepdownStrategy};
+    use geo::{Geometry, coord};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_linestring_to_hpgl() {
+        let line = LineString::new(vec![
+            coord! {x: 0., y: 0.},
+            coord! {x: 10., y: 0.},
+            coord! {x: 10., y: 0.001}, // Rounds away to nothing.
+            coord! {x: 10., y: 5.},
+        ]);
+        let hpgl = linestring_to_hpgl(&line);
+        assert_eq!(hpgl, vec!["PU0,0;", "PD400,0,400,200;"]);
+    }
+
+    #[test]
+    fn test_parse_hpgl_moves() {
+        let moves = parse_hpgl_moves("PU40,80;PD400,0,400,200;PU;");
+        assert_eq!(
+            moves,
+            vec![(false, 1., 2.), (true, 10., 0.), (true, 10., 5.)]
+        );
+    }
+
+    #[test]
+    fn test_post_hpgl() {
+        let mut project = Project::new();
+        let mut machine = MachineConfig::default();
+        machine.set_variant(MachineVariant::HPGL);
+        project.set_machine(Some(machine));
+        project.set_origin(&Some((0., 100.)));
+        let pen = PenDetail {
+            identity: Uuid::new_v4(),
+            tool_id: 3,
+            ..PenDetail::default()
+        };
+        project.pens.push(pen.clone());
+        project.plot_geometry.push(BAPGeometry {
+            pen_uuid: pen.identity,
+            name: "line".to_string(),
+            geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(vec![
+                coord! {x: 10., y: 90.},
+                coord! {x: 20., y: 90.},
+            ]))),
+            keepdown_strategy: KeepdownStrategy::None,
+            pinned: false,
+            marks: false,
+        });
+        let (program, _summary) =
+            post_hpgl(&project, &PostOptions::default()).expect("HPGL post failed");
+        assert_eq!(program.first().unwrap(), "IN;");
+        assert!(program.contains(&"SP3;".to_string()));
+        // The optimizer is free to flip the line, so accept either direction.
+        assert!(
+            (program.contains(&"PU400,400;".to_string())
+                && program.contains(&"PD800,400;".to_string()))
+                || (program.contains(&"PU800,400;".to_string())
+                    && program.contains(&"PD400,400;".to_string()))
+        );
+        assert_eq!(program.last().unwrap(), "SP0;");
+    }
+}
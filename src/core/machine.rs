@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::ffi::OsString;
 use std::io::BufWriter;
 use std::str::FromStr;
@@ -6,22 +7,175 @@ use std::{fmt::Debug, fs::File, path::PathBuf};
 
 use anyhow::Result as AnyResult;
 use anyhow::anyhow;
+use nalgebra::{Affine2, Matrix3};
 use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
 use tera::Tera;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum MachineVariant {
     GRBL,
     HPGL,
 }
 
+impl MachineVariant {
+    pub fn all() -> Vec<MachineVariant> {
+        vec![MachineVariant::GRBL, MachineVariant::HPGL]
+    }
+}
+
+impl std::fmt::Display for MachineVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineVariant::GRBL => write!(f, "GRBL (GCode)"),
+            MachineVariant::HPGL => write!(f, "HPGL"),
+        }
+    }
+}
+
 impl Default for MachineVariant {
     fn default() -> Self {
         MachineVariant::GRBL
     }
 }
 
+/// How the sender decides it can send another line.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Streaming {
+    /// Waits for each line's ok, which suits Marlin style firmware.
+    #[default]
+    OkCounting,
+    /// Keeps the controller's receive buffer full, tracking how many bytes
+    /// are in it from the oks that have come back. GRBL's preferred way.
+    CharacterCounting,
+}
+
+impl Streaming {
+    pub fn all() -> Vec<Streaming> {
+        vec![Streaming::OkCounting, Streaming::CharacterCounting]
+    }
+}
+
+impl std::fmt::Display for Streaming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Streaming::OkCounting => write!(f, "Ok counting"),
+            Streaming::CharacterCounting => write!(f, "Character counting"),
+        }
+    }
+}
+
+/// The corner of the bed the machine homes to, looking down on it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum OriginCorner {
+    #[default]
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+impl OriginCorner {
+    pub fn all() -> Vec<OriginCorner> {
+        vec![
+            OriginCorner::BottomLeft,
+            OriginCorner::BottomRight,
+            OriginCorner::TopLeft,
+            OriginCorner::TopRight,
+        ]
+    }
+}
+
+impl std::fmt::Display for OriginCorner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OriginCorner::BottomLeft => write!(f, "Bottom left"),
+            OriginCorner::BottomRight => write!(f, "Bottom right"),
+            OriginCorner::TopLeft => write!(f, "Top left"),
+            OriginCorner::TopRight => write!(f, "Top right"),
+        }
+    }
+}
+
+/// Where home is and which way the machine's axes run. By default X runs
+/// right and Y runs up from home. Inverting an axis makes it count down
+/// into the bed instead, and swapping puts machine X along the bed's height.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct MachineAxes {
+    #[serde(default)]
+    pub origin_corner: OriginCorner,
+    #[serde(default)]
+    pub invert_x: bool,
+    #[serde(default)]
+    pub invert_y: bool,
+    #[serde(default)]
+    pub swap_xy: bool,
+}
+
+/// Pen heights for machines with a real Z axis instead of a servo, in mm
+/// with the paper at zero.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PenZ {
+    /// Height for tool changes and the start and end of the job.
+    pub travel: f64,
+    /// Height for moves between lines.
+    pub skim: f64,
+    /// Height the pen draws at.
+    pub contact: f64,
+    /// How much deeper (mm) a pen with a stroke density of 1.0 presses
+    /// than one with 0. None draws every pen at the contact height.
+    #[serde(default)]
+    pub pressure: Option<f64>,
+}
+
+impl Default for PenZ {
+    fn default() -> Self {
+        Self {
+            travel: 5.,
+            skim: 1.,
+            contact: 0.,
+            pressure: None,
+        }
+    }
+}
+
+impl PenZ {
+    /// The drawing height for a pen with the given stroke density.
+    pub fn contact_for(&self, density: f64) -> f64 {
+        match self.pressure {
+            Some(depth) => self.contact - depth * density.clamp(0., 1.),
+            None => self.contact,
+        }
+    }
+}
+
+/// A slot in an automatic tool changer, like a pen carousel or rack.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ToolSlot {
+    pub tool_id: usize,
+    /// Where the carriage picks the pen up, in machine coordinates.
+    pub position: (f64, f64),
+    /// Where the carriage comes in from, relative to position. It travels
+    /// here, feeds into the slot, and backs out the same way.
+    pub approach: (f64, f64),
+    /// Template run in the slot to take hold of the pen.
+    pub grab: String,
+    /// Template run in the slot to let go of the pen.
+    pub release: String,
+}
+
+impl Default for ToolSlot {
+    fn default() -> Self {
+        Self {
+            tool_id: 1,
+            position: (0., 0.),
+            approach: (0., 10.),
+            grab: "G4 P500 ; GRAB T{{tool_id}}".to_string(),
+            release: "G4 P500 ; RELEASE T{{tool_id}}".to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct MachineConfig {
     name: String,
@@ -32,6 +186,45 @@ pub struct MachineConfig {
     feedrate: f64,
     #[serde(default)]
     variant: MachineVariant,
+    #[serde(default)]
+    modal: bool,
+    /// Per-axis acceleration in mm/s², for the time estimate.
+    #[serde(default = "default_acceleration")]
+    acceleration: (f64, f64),
+    /// G0 travel speed in mm/min, for the time estimate.
+    #[serde(default = "default_rapid_feedrate")]
+    rapid_feedrate: f64,
+    /// Z heights, if the pen is on a Z axis. Templates get them as `zmm`.
+    #[serde(default)]
+    pen_z: Option<PenZ>,
+    #[serde(default)]
+    axes: MachineAxes,
+    /// Where each tool's tip sits relative to the carriage's reference
+    /// point (mm, machine axes), keyed by tool_id.
+    #[serde(default)]
+    tool_offsets: BTreeMap<usize, (f64, f64)>,
+    /// Tool changer slots. Pens with a slot are swapped automatically,
+    /// the rest still go through the toolchange template.
+    #[serde(default)]
+    tool_slots: Vec<ToolSlot>,
+    #[serde(default)]
+    streaming: Streaming,
+    /// The controller's serial receive buffer in bytes, for character
+    /// counting.
+    #[serde(default = "default_rx_buffer")]
+    rx_buffer: usize,
+}
+
+fn default_acceleration() -> (f64, f64) {
+    (1000., 1000.)
+}
+
+fn default_rapid_feedrate() -> f64 {
+    3000.
+}
+
+fn default_rx_buffer() -> usize {
+    128
 }
 
 impl Debug for MachineConfig {
@@ -42,6 +235,16 @@ impl Debug for MachineConfig {
             .field("keepdown", &self.keepdown)
             .field("limits", &self.limits)
             .field("feedrate", &self.feedrate)
+            .field("variant", &self.variant)
+            .field("modal", &self.modal)
+            .field("acceleration", &self.acceleration)
+            .field("rapid_feedrate", &self.rapid_feedrate)
+            .field("pen_z", &self.pen_z)
+            .field("axes", &self.axes)
+            .field("tool_offsets", &self.tool_offsets)
+            .field("tool_slots", &self.tool_slots)
+            .field("streaming", &self.streaming)
+            .field("rx_buffer", &self.rx_buffer)
             .finish()
     }
 }
@@ -124,6 +327,153 @@ impl MachineConfig {
         self.skim = skim
     }
 
+    pub fn set_variant(&mut self, variant: MachineVariant) {
+        self.variant = variant;
+    }
+
+    pub fn set_modal(&mut self, modal: bool) {
+        self.modal = modal;
+    }
+
+    pub fn set_streaming(&mut self, streaming: Streaming) {
+        self.streaming = streaming;
+    }
+
+    pub fn set_rx_buffer(&mut self, rx_buffer: usize) {
+        self.rx_buffer = rx_buffer;
+    }
+
+    pub fn set_acceleration(&mut self, acceleration: (f64, f64)) {
+        self.acceleration = acceleration;
+    }
+
+    pub fn acceleration(&self) -> (f64, f64) {
+        self.acceleration
+    }
+
+    pub fn set_rapid_feedrate(&mut self, rapid_feedrate: f64) {
+        self.rapid_feedrate = rapid_feedrate;
+    }
+
+    pub fn rapid_feedrate(&self) -> f64 {
+        self.rapid_feedrate
+    }
+
+    pub fn set_pen_z(&mut self, pen_z: Option<PenZ>) {
+        self.pen_z = pen_z;
+    }
+
+    pub fn pen_z(&self) -> Option<PenZ> {
+        self.pen_z.clone()
+    }
+
+    pub fn set_axes(&mut self, axes: MachineAxes) {
+        self.axes = axes;
+    }
+
+    pub fn axes(&self) -> MachineAxes {
+        self.axes.clone()
+    }
+
+    pub fn set_tool_offsets(&mut self, tool_offsets: BTreeMap<usize, (f64, f64)>) {
+        self.tool_offsets = tool_offsets;
+    }
+
+    pub fn tool_offsets(&self) -> BTreeMap<usize, (f64, f64)> {
+        self.tool_offsets.clone()
+    }
+
+    /// The offset for a tool, or none at all if it isn't in the table.
+    pub fn tool_offset(&self, tool_id: usize) -> (f64, f64) {
+        self.tool_offsets.get(&tool_id).copied().unwrap_or((0., 0.))
+    }
+
+    pub fn set_tool_slots(&mut self, tool_slots: Vec<ToolSlot>) {
+        self.tool_slots = tool_slots;
+    }
+
+    pub fn tool_slots(&self) -> Vec<ToolSlot> {
+        self.tool_slots.clone()
+    }
+
+    pub fn tool_slot(&self, tool_id: usize) -> Option<ToolSlot> {
+        self.tool_slots
+            .iter()
+            .find(|slot| slot.tool_id == tool_id)
+            .cloned()
+    }
+
+    /// The machine's travel as laid out in the view, which is the limits
+    /// turned on their side if the axes are swapped.
+    pub fn bed_size(&self) -> (f64, f64) {
+        if self.axes.swap_xy {
+            (self.limits.1, self.limits.0)
+        } else {
+            self.limits
+        }
+    }
+
+    /// Takes bed coordinates (mm from the bed's bottom left corner, Y up)
+    /// to machine coordinates, for the home corner and axis directions.
+    pub fn bed_to_machine(&self) -> Affine2<f64> {
+        let (width, height) = self.bed_size();
+        let (from_right, from_top) = match self.axes.origin_corner {
+            OriginCorner::BottomLeft => (false, false),
+            OriginCorner::BottomRight => (true, false),
+            OriginCorner::TopLeft => (false, true),
+            OriginCorner::TopRight => (true, true),
+        };
+        // Distance from home along the bed, in the bed's own directions.
+        let from_home = Matrix3::new(
+            if from_right { -1. } else { 1. },
+            0.,
+            if from_right { width } else { 0. },
+            0.,
+            if from_top { -1. } else { 1. },
+            if from_top { height } else { 0. },
+            0.,
+            0.,
+            1.,
+        );
+        let swap = if self.axes.swap_xy {
+            Matrix3::new(0., 1., 0., 1., 0., 0., 0., 0., 1.)
+        } else {
+            Matrix3::identity()
+        };
+        let invert = Matrix3::new(
+            if self.axes.invert_x { -1. } else { 1. },
+            0.,
+            0.,
+            0.,
+            if self.axes.invert_y { -1. } else { 1. },
+            0.,
+            0.,
+            0.,
+            1.,
+        );
+        Affine2::from_matrix_unchecked(invert * swap * from_home)
+    }
+
+    /// Whether the posted G-code gets run through the ModalCompressor.
+    pub fn modal(&self) -> bool {
+        self.modal
+    }
+
+    pub fn streaming(&self) -> Streaming {
+        self.streaming.clone()
+    }
+
+    pub fn rx_buffer(&self) -> usize {
+        self.rx_buffer
+    }
+
+    /// Whether the post template defines the given section.
+    pub fn has_post_section(&self, name: &str) -> bool {
+        self.post_template
+            .iter()
+            .any(|(section, _)| section == name)
+    }
+
     pub fn post_template(&self) -> AnyResult<Tera> {
         let mut tera = Tera::default();
         tera.add_raw_templates(self.post_template.clone().into_iter())?;
@@ -146,6 +496,10 @@ impl MachineConfig {
         self.feedrate
     }
 
+    pub fn variant(&self) -> MachineVariant {
+        self.variant.clone()
+    }
+
     pub fn bapv1() -> Self {
         let bap_top = 4.;
         let bap_bottom = 13.;
@@ -199,12 +553,20 @@ impl MachineConfig {
             ),
             (
                 "moveto".into(),
-                "G0 X{{xmm|round(precision=2)}} Y{{ymm|round(precision=2)}} ; NEW LINE START"
+                "G0 X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} ; NEW LINE START"
                     .to_string(),
             ),
             (
                 "lineto".into(),
-                "G01 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=2)}} Y{{ymm|round(precision=2)}}".to_string(),
+                "G01 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}}".to_string(),
+            ),
+            (
+                "arc_cw".into(),
+                "G02 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} I{{imm|round(precision=3)}} J{{jmm|round(precision=3)}}".to_string(),
+            ),
+            (
+                "arc_ccw".into(),
+                "G03 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} I{{imm|round(precision=3)}} J{{jmm|round(precision=3)}}".to_string(),
             ),
             // ("coords".into(),
             //     "X{{xmm|round(precision=2)}} Y{{ymm|round(precision=2)}}".to_string()),
@@ -221,8 +583,65 @@ impl MachineConfig {
             limits: (235., 235.),
             feedrate: 1200.,
             variant: Default::default(),
+            modal: false,
+            acceleration: default_acceleration(),
+            rapid_feedrate: default_rapid_feedrate(),
+            pen_z: None,
+            axes: MachineAxes::default(),
+            tool_offsets: BTreeMap::new(),
+            tool_slots: Vec::new(),
+            streaming: Streaming::default(),
+            rx_buffer: default_rx_buffer(),
         }
     }
+
+    /// Templates for a plain GRBL machine with the pen on a Z axis. Every
+    /// lift and drop goes to `zmm`, which the post sets for each section.
+    pub fn z_axis_templates() -> Vec<(String, String)> {
+        let plunge = "G1 F600 Z{{zmm|round(precision=3)}}";
+        vec![
+            (
+                "prelude".into(),
+                "G21\nG90\nG0 Z{{zmm|round(precision=3)}}\n$H ; HOME".to_string(),
+            ),
+            (
+                "epilog".into(),
+                "G0 Z{{zmm|round(precision=3)}}\nG0 X0 Y0\nM2 ; FINISHED".to_string(),
+            ),
+            (
+                "penup".into(),
+                "G0 Z{{zmm|round(precision=3)}} ; PENUP".to_string(),
+            ),
+            (
+                "penup_skim".into(),
+                "G0 Z{{zmm|round(precision=3)}} ; PENUP_SKIM".to_string(),
+            ),
+            ("pendrop".into(), plunge.to_string()),
+            ("pendown".into(), format!("{} ; PENDOWN", plunge)),
+            ("pendown_skim".into(), format!("{} ; PENDOWN SKIM", plunge)),
+            (
+                "moveto".into(),
+                "G0 X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} ; NEW LINE START"
+                    .to_string(),
+            ),
+            (
+                "lineto".into(),
+                "G01 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}}".to_string(),
+            ),
+            (
+                "arc_cw".into(),
+                "G02 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} I{{imm|round(precision=3)}} J{{jmm|round(precision=3)}}".to_string(),
+            ),
+            (
+                "arc_ccw".into(),
+                "G03 F{{feedrate|round(precision=2)}} X{{xmm|round(precision=3)}} Y{{ymm|round(precision=3)}} I{{imm|round(precision=3)}} J{{jmm|round(precision=3)}}".to_string(),
+            ),
+            (
+                "toolchange".into(),
+                "G0 Z{{zmm|round(precision=3)}}\n$M06 T{{tool_id}}".to_string(),
+            ),
+        ]
+    }
 }
 
 impl Default for MachineConfig {
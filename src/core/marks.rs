@@ -0,0 +1,208 @@
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+use geo::{Geometry, LineString, MultiLineString, Rect, coord};
+use std::f64::consts::PI;
+
+use super::commands::{ApplicationStateChangeMsg, MarksConfig, MarksTarget};
+use super::project::{BAPGeometry, GeometryKind, KeepdownStrategy, Project};
+
+/// The name the marks geometry is given, for the layer list.
+pub const MARKS_NAME: &str = "Crop marks";
+
+/// How many segments the circle around each crosshair gets.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Drops the marks geometry, if there is any.
+pub fn remove_marks(project: &mut Project) {
+    project.plot_geometry.retain(|geometry| !geometry.marks);
+}
+
+/// Redraws the project's marks around the current extents or paper, or
+/// just removes them if the project has none configured.
+pub fn regenerate_marks(project: &mut Project) -> AnyResult<()> {
+    remove_marks(project);
+    let Some(config) = project.marks.clone() else {
+        return Ok(());
+    };
+    if project.plot_geometry.is_empty() {
+        return Ok(());
+    }
+    let pen = config
+        .pen_uuid
+        .and_then(|uuid| project.pen_by_uuid(uuid))
+        .or(project.pens.first().cloned())
+        .ok_or(anyhow!("There's no pen to draw the marks with."))?;
+    let target = match config.target {
+        MarksTarget::Extents => project.calc_extents(),
+        MarksTarget::Paper => {
+            let (width, height) = project.paper.oriented_dimensions();
+            let (x, y) = project.origin().unwrap_or((0., height));
+            Rect::new(coord! {x: x, y: y - height}, coord! {x: x + width, y: y})
+        }
+    };
+    project.plot_geometry.push(BAPGeometry {
+        pen_uuid: pen.identity,
+        name: MARKS_NAME.to_string(),
+        geometry: GeometryKind::Stroke(Geometry::MultiLineString(marks_lines(&target, &config))),
+        keepdown_strategy: KeepdownStrategy::None,
+        pinned: false,
+        marks: true,
+    });
+    Ok(())
+}
+
+/// The trim box the marks are placed around: the extents grown by the
+/// bleed, or the paper shrunk by it.
+fn trim_box(target: &Rect<f64>, config: &MarksConfig) -> Rect<f64> {
+    let bleed = match config.target {
+        MarksTarget::Extents => config.bleed,
+        MarksTarget::Paper => -config.bleed,
+    };
+    Rect::new(
+        coord! {x: target.min().x - bleed, y: target.min().y - bleed},
+        coord! {x: target.max().x + bleed, y: target.max().y + bleed},
+    )
+}
+
+/// All the marks as lines, in project coordinates.
+fn marks_lines(target: &Rect<f64>, config: &MarksConfig) -> MultiLineString<f64> {
+    let trim = trim_box(target, config);
+    let mut lines = Vec::new();
+    if config.bleed_box {
+        lines.push(trim.to_polygon().exterior().clone());
+    }
+    let (near, far) = (config.crop_offset, config.crop_offset + config.crop_length);
+    // Marks around the paper have nowhere to go but inwards.
+    let out = match config.target {
+        MarksTarget::Extents => 1.,
+        MarksTarget::Paper => -1.,
+    };
+    if config.crop_marks {
+        // Each corner gets a mark carrying on along both of its edges,
+        // pointing away from the box (or into it, for the paper).
+        for (x, dx) in [(trim.min().x, -out), (trim.max().x, out)] {
+            for (y, dy) in [(trim.min().y, -out), (trim.max().y, out)] {
+                lines.push(LineString::new(vec![
+                    coord! {x: x + dx * near, y: y},
+                    coord! {x: x + dx * far, y: y},
+                ]));
+                lines.push(LineString::new(vec![
+                    coord! {x: x, y: y + dy * near},
+                    coord! {x: x, y: y + dy * far},
+                ]));
+            }
+        }
+    }
+    if config.crosshairs {
+        let center = trim.center();
+        let gap = out * (config.crop_offset + config.crosshair_size);
+        for (x, y) in [
+            (trim.min().x - gap, center.y),
+            (trim.max().x + gap, center.y),
+            (center.x, trim.min().y - gap),
+            (center.x, trim.max().y + gap),
+        ] {
+            lines.extend(crosshair(x, y, config.crosshair_size));
+        }
+    }
+    MultiLineString::new(lines)
+}
+
+/// A cross with a circle around it, the usual registration target.
+fn crosshair(x: f64, y: f64, size: f64) -> Vec<LineString<f64>> {
+    let radius = size * 0.6;
+    vec![
+        LineString::new(vec![coord! {x: x - size, y: y}, coord! {x: x + size, y: y}]),
+        LineString::new(vec![coord! {x: x, y: y - size}, coord! {x: x, y: y + size}]),
+        LineString::new(
+            (0..=CIRCLE_SEGMENTS)
+                .map(|idx| {
+                    let angle = 2. * PI * idx as f64 / CIRCLE_SEGMENTS as f64;
+                    coord! {x: x + radius * angle.cos(), y: y + radius * angle.sin()}
+                })
+                .collect(),
+        ),
+    ]
+}
+
+impl super::ApplicationCore {
+    /// Sets (or clears) the project's marks, and redraws them.
+    pub fn handle_set_marks(&mut self, marks: Option<MarksConfig>) {
+        self.project.marks = marks;
+        regenerate_marks(&mut self.project).unwrap_or_else(|err| {
+            self.yolo_app_state_change(ApplicationStateChangeMsg::Error(format!(
+                "Failed to generate marks: {}",
+                err
+            )))
+        });
+        self.rebuild_after_content_change();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::project::PenDetail;
+    use geo::BoundingRect;
+
+    #[test]
+    fn test_regenerate_marks() {
+        let mut project = Project::new();
+        let pen = PenDetail::default();
+        project.pens.push(pen.clone());
+        project.plot_geometry.push(BAPGeometry {
+            pen_uuid: pen.identity,
+            name: "Box".to_string(),
+            geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(vec![
+                coord! {x: 0., y: 0.},
+                coord! {x: 100., y: 50.},
+            ]))),
+            keepdown_strategy: KeepdownStrategy::None,
+            pinned: false,
+            marks: false,
+        });
+        project.marks = Some(MarksConfig::default());
+        regenerate_marks(&mut project).expect("Failed to generate marks");
+        // Regenerating replaces the marks rather than stacking them up.
+        regenerate_marks(&mut project).expect("Failed to generate marks");
+        assert_eq!(project.plot_geometry.len(), 2);
+        let marks = project.plot_geometry.last().unwrap();
+        assert_eq!(marks.name, MARKS_NAME);
+        // Eight crop marks, and four crosshairs of a cross and a circle.
+        assert_eq!(marks.lines().0.len(), 8 + 4 * 3);
+        // The left crosshair sits 6mm out from the 3mm bleed, and reaches
+        // out another 4mm.
+        let bounds = marks.geometry().bounding_rect().unwrap();
+        assert!((bounds.min().x + 13.).abs() < 1e-9);
+        assert!((bounds.max().y - 63.).abs() < 1e-9);
+
+        // Renaming the marks (like transforms do) doesn't lose track of them.
+        project.plot_geometry.last_mut().unwrap().name = "geometry 1".to_string();
+        regenerate_marks(&mut project).expect("Failed to generate marks");
+        assert_eq!(project.plot_geometry.len(), 2);
+
+        // Around the paper, everything has to stay on the sheet.
+        project.set_origin(&Some((0., 300.)));
+        project.marks = Some(MarksConfig {
+            target: MarksTarget::Paper,
+            ..MarksConfig::default()
+        });
+        regenerate_marks(&mut project).expect("Failed to generate marks");
+        let (width, height) = project.paper.oriented_dimensions();
+        let bounds = project
+            .plot_geometry
+            .last()
+            .unwrap()
+            .geometry()
+            .bounding_rect()
+            .unwrap();
+        assert!(bounds.min().x >= 0.);
+        assert!(bounds.max().x <= width);
+        assert!(bounds.min().y >= 300. - height);
+        assert!(bounds.max().y <= 300.);
+
+        project.marks = None;
+        regenerate_marks(&mut project).expect("Failed to remove marks");
+        assert_eq!(project.plot_geometry.len(), 1);
+    }
+}
@@ -4,21 +4,37 @@ use std::time::{Duration, Instant};
 
 use egui::{ColorImage, Context};
 
+pub(crate) mod arcs;
 pub(crate) mod commands;
 pub(crate) mod config;
 pub(crate) mod core_run;
+pub(crate) mod estimate;
+pub(crate) mod export;
 pub(crate) mod group_ungroup;
+pub(crate) mod hpgl;
 pub(crate) mod machine;
+pub(crate) mod marks;
+pub(crate) mod modal;
+pub(crate) mod optimize;
 pub(crate) mod paper;
 pub(crate) mod pick_map;
 pub(crate) mod post;
+pub(crate) mod post_context;
 pub(crate) mod project;
 pub(crate) mod render_plot;
 pub(crate) mod render_preview;
 pub(crate) mod render_source;
+pub(crate) mod resume;
 pub(crate) mod selections;
 pub(crate) mod sender;
 pub(crate) mod serial;
+pub(crate) mod sim;
+pub(crate) mod simplify;
+pub(crate) mod status;
+pub(crate) mod tcp;
+pub(crate) mod template_lint;
+pub(crate) mod tile;
+pub(crate) mod validate;
 
 use commands::{ApplicationStateChangeMsg, ViewCommand};
 use gcode::GCode;
@@ -29,6 +45,7 @@ use crate::core::project::Project;
 use crate::core::render_plot::render_plot_preview;
 use crate::view_model::view_model_patch::ViewModelPatch;
 use machine::MachineConfig;
+use post::PostSummary;
 use sender::{PlotterCommand, PlotterConnection, PlotterResponse, PlotterState};
 /// The actual application core that does shit.
 ///
@@ -55,8 +72,11 @@ pub struct ApplicationCore {
     plot_receiver: Receiver<PlotterResponse>,
     last_serial_scan: Instant,
     program: Option<Vec<String>>,
+    post_summary: Option<PostSummary>,
     gcode: Option<Vec<GCode>>,
     progress: (usize, usize, usize),
+    // Where the plotter last reported the pen (X, Y), in machine coordinates.
+    pen_position: Option<(f64, f64)>,
     state: PlotterState,
 }
 
@@ -97,8 +117,10 @@ impl ApplicationCore {
             last_serial_scan: Instant::now(),
             last_rendered: Instant::now(),
             program: None,
+            post_summary: None,
             gcode: None,
             progress: (0, 0, 0),
+            pen_position: None,
             state: PlotterState::Disconnected,
             picked: None,
             config,
@@ -236,6 +258,17 @@ impl ApplicationCore {
         });
     }
 
+    /// Lets the sender know which dialect the project's machine speaks, so
+    /// it knows whether to wait for oks, and how to pace the lines.
+    pub fn sync_plotter_variant(&mut self) {
+        let machine = self.project.machine().unwrap_or_default();
+        self.yolo_send_plotter_cmd(PlotterCommand::SetVariant(machine.variant()));
+        self.yolo_send_plotter_cmd(PlotterCommand::SetStreaming(
+            machine.streaming(),
+            machine.rx_buffer(),
+        ));
+    }
+
     pub fn handle_plotter_response(
         &mut self,
         response: PlotterResponse,
@@ -244,10 +277,20 @@ impl ApplicationCore {
         match &response {
             PlotterResponse::Ok(_plotter_command, _) => (),
             PlotterResponse::Err(_plotter_command, _) => {}
+            PlotterResponse::Status(status) => {
+                self.pen_position = status.position.map(|(x, y, _z)| (x, y));
+            }
             PlotterResponse::State(plotter_state) => {
-                if let PlotterState::Running(line, of, _something) = plotter_state {
-                    self.progress = (*line as usize, *of as usize, *_something as usize);
+                if let PlotterState::Running(line, of, oks) = plotter_state {
+                    self.progress = (*line as usize, *of as usize, *oks as usize);
                 };
+                if *plotter_state == PlotterState::Ready {
+                    // Finished, so nothing's left waiting on an ok.
+                    self.progress.2 = 0;
+                }
+                if *plotter_state == PlotterState::Disconnected {
+                    self.pen_position = None;
+                }
                 self.state = plotter_state.clone();
             }
             PlotterResponse::Loaded(_msg) => {
@@ -0,0 +1,293 @@
+/// Splits a line of G-code into its words, ignoring comments. Words that
+/// don't have a number (like the X in `G28 X Y`) come back as NaN.
+pub fn parse_words(line: &str) -> Vec<(char, f64)> {
+    let code = line.split(';').next().unwrap_or("");
+    let mut words = Vec::new();
+    let mut chars = code.chars().peekable();
+    let mut in_paren = false;
+    while let Some(c) = chars.next() {
+        if in_paren {
+            in_paren = c != ')';
+            continue;
+        }
+        if c == '(' {
+            in_paren = true;
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            continue;
+        }
+        let mut number = String::new();
+        while let Some(n) = chars.peek() {
+            if n.is_ascii_digit() || *n == '.' || *n == '-' || *n == '+' {
+                number.push(*n);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        words.push((c.to_ascii_uppercase(), number.parse().unwrap_or(f64::NAN)));
+    }
+    words
+}
+
+/// Words we know how to drop when they repeat the modal state.
+const MODAL_AXES: [char; 3] = ['X', 'Y', 'Z'];
+
+/// Tracks GRBL/Marlin modal state (motion mode, feedrate and position)
+/// across posted lines, dropping the words that wouldn't change anything.
+/// Anything it doesn't understand resets what it knows, so the worst case
+/// is just less compression. Nothing is compressed in relative (G91) mode.
+#[derive(Debug, Default, Clone)]
+pub struct ModalCompressor {
+    motion: Option<f64>,
+    feed: Option<f64>,
+    axes: [Option<f64>; 3],
+    relative: bool,
+}
+
+impl ModalCompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets everything but the distance mode, which only G90/G91 change.
+    fn reset(&mut self) {
+        *self = Self {
+            relative: self.relative,
+            ..Self::default()
+        };
+    }
+
+    /// Compresses a single line, returning None if it turned out to be a
+    /// move to where we already are.
+    pub fn compress(&mut self, line: &str) -> Option<String> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            return Some(line.to_string());
+        }
+        // GRBL $ commands (like our $M06 toolchange) can do anything.
+        if trimmed.starts_with('$') {
+            self.reset();
+            return Some(line.to_string());
+        }
+        let words = parse_words(trimmed);
+        let g_words: Vec<f64> = words
+            .iter()
+            .filter(|(letter, _)| *letter == 'G')
+            .map(|(_, value)| *value)
+            .collect();
+        if g_words.contains(&90.) || g_words.contains(&91.) {
+            self.reset();
+            self.relative = g_words.contains(&91.);
+            return Some(line.to_string());
+        }
+        if self.relative {
+            // A repeated relative move is a second move, so leave it be.
+            return Some(line.to_string());
+        }
+        let motion = match g_words.as_slice() {
+            [] => self.motion,
+            [g] if [0., 1., 2., 3.].contains(g) => Some(*g),
+            // Dwells don't touch modal state.
+            [g] if *g == 4. => return Some(line.to_string()),
+            _ => {
+                self.reset();
+                return Some(line.to_string());
+            }
+        };
+        let has_motion_words = words
+            .iter()
+            .any(|(letter, _)| MODAL_AXES.contains(letter) || *letter == 'F');
+        if !has_motion_words
+            || words
+                .iter()
+                .any(|(letter, value)| value.is_nan() || *letter == 'M')
+        {
+            // Either a bare modal change, or something like M280 S10.
+            if !g_words.is_empty() {
+                self.reset();
+            }
+            return Some(line.to_string());
+        }
+        let Some(motion) = motion else {
+            // Coordinates with no motion mode we know of, so pass through.
+            return Some(line.to_string());
+        };
+        let code = trimmed.split(';').next().unwrap_or("");
+        if code
+            .split_whitespace()
+            .any(|token| parse_words(token).len() != 1)
+        {
+            // Packed words like G1X10Y20, which we'd rather not pick apart.
+            self.reset();
+            return Some(line.to_string());
+        }
+        let is_arc = motion == 2. || motion == 3.;
+
+        let mut next = self.clone();
+        next.motion = Some(motion);
+        let mut out: Vec<String> = Vec::new();
+        // Arcs are left whole, since some firmware is fussy about arcs
+        // missing an axis.
+        let mut changed = is_arc;
+        if self.motion != Some(motion) {
+            out.push(format!("G{}", motion));
+        }
+        for token in code.split_whitespace() {
+            let (letter, value) = parse_words(token)[0];
+            match letter {
+                'G' => (),
+                'F' => {
+                    if self.feed != Some(value) {
+                        next.feed = Some(value);
+                        changed = true;
+                        out.push(token.to_string());
+                    }
+                }
+                'X' | 'Y' | 'Z' => {
+                    let axis = MODAL_AXES.iter().position(|a| *a == letter).unwrap_or(0);
+                    if is_arc || self.axes[axis] != Some(value) {
+                        next.axes[axis] = Some(value);
+                        changed = true;
+                        out.push(token.to_string());
+                    }
+                }
+                _ => {
+                    // Not tracked (like S or T), so it always counts.
+                    changed = true;
+                    out.push(token.to_string());
+                }
+            }
+        }
+        if !changed {
+            return None;
+        }
+        *self = next;
+        if let Some((_, comment)) = trimmed.split_once(';') {
+            out.push(format!(";{}", comment));
+        }
+        Some(out.join(" "))
+    }
+}
+
+/// Runs a whole program through a fresh ModalCompressor. Marks are line
+/// numbers into the program (in order), and get moved to match the output.
+/// The modal state is forgotten at each mark, so the program can be split
+/// there and each piece still stands on its own.
+pub fn compress_program(program: &[String], marks: &mut [usize]) -> Vec<String> {
+    let mut compressor = ModalCompressor::new();
+    let mut out: Vec<String> = Vec::new();
+    let mut marks = marks.iter_mut().peekable();
+    for (idx, line) in program.iter().enumerate() {
+        while let Some(mark) = marks.next_if(|mark| **mark <= idx) {
+            *mark = out.len();
+            compressor.reset();
+        }
+        if let Some(line) = compressor.compress(line) {
+            out.push(line);
+        }
+    }
+    for mark in marks {
+        *mark = out.len();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_words() {
+        assert_eq!(
+            parse_words("G01 F1200 X10.5 Y-3 ; comment X99"),
+            vec![('G', 1.), ('F', 1200.), ('X', 10.5), ('Y', -3.)]
+        );
+        let words = parse_words("G28 X Y");
+        assert_eq!(words.len(), 3);
+        assert!(words[1].1.is_nan());
+    }
+
+    #[test]
+    fn test_compress_program() {
+        let program: Vec<String> = vec![
+            "G0 X10 Y10 ; NEW LINE START",
+            "M280 S13",
+            "G4 P20",
+            "G01 F1200 X20 Y10",
+            "G01 F1200 X20 Y20",
+            "G01 F1200 X20 Y20",
+            "G01 F1500 X30 Y20",
+            "G03 F1500 X40 Y20 I5 J0",
+            "G0 X40 Y25",
+            "G1 F1500 X40 Y25",
+            "G1X50Y25",
+            "G0 X40 Y25",
+            "$M06 T2",
+            "G0 X40 Y25",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+        let mut marks = vec![4, 12];
+        assert_eq!(
+            compress_program(&program, &mut marks),
+            vec![
+                "G0 X10 Y10 ; NEW LINE START",
+                "M280 S13",
+                "G4 P20",
+                "G1 F1200 X20",
+                // Forgets everything at a mark.
+                "G1 F1200 X20 Y20",
+                "F1500 X30",
+                "G3 X40 Y20 I5 J0",
+                "G0 Y25",
+                "G1X50Y25",
+                "G0 X40 Y25",
+                "$M06 T2",
+                "G0 X40 Y25",
+            ]
+        );
+        assert_eq!(marks, vec![4, 10]);
+
+        let program: Vec<String> = vec![
+            "G1 F1200 X1 Y1",
+            "G91",
+            "G1 F1200 X1 Y1",
+            "G1 F1200 X1 Y1",
+            "$M06 T2",
+            "G1 F1200 X1 Y1",
+            "G90",
+            "G1 F1200 X1 Y1",
+            "G1 F1200 X1 Y1",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(
+            compress_program(&program, &mut []),
+            vec![
+                "G1 F1200 X1 Y1",
+                "G91",
+                "G1 F1200 X1 Y1",
+                "G1 F1200 X1 Y1",
+                "$M06 T2",
+                "G1 F1200 X1 Y1",
+                "G90",
+                // Back in absolute mode, where it's forgotten the position.
+                "G1 F1200 X1 Y1",
+            ]
+        );
+
+        // A power change on its own still goes out.
+        let program: Vec<String> = vec!["G1 F1200 X10 Y10 S500", "G1 F1200 X10 Y10 S800"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            compress_program(&program, &mut []),
+            vec!["G1 F1200 X10 Y10 S500", "S800"]
+        );
+    }
+}
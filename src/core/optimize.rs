@@ -0,0 +1,265 @@
+use std::time::{Duration, Instant};
+
+use aoer_plotty_rs::optimizer::{OptimizationStrategy, Optimizer};
+use geo::{Coord, MultiLineString};
+
+use super::config::{PathStrategy, PostOptions};
+
+fn distance(a: &Coord<f64>, b: &Coord<f64>) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// Total pen-up distance needed to draw the lines in order, optionally
+/// starting from wherever the pen was left.
+pub fn travel_distance(lines: &MultiLineString<f64>, from: Option<Coord<f64>>) -> f64 {
+    let mut position = from;
+    let mut travel = 0.;
+    for line in lines.0.iter() {
+        if let (Some(first), Some(last)) = (line.0.first(), line.0.last()) {
+            if let Some(position) = position {
+                travel += distance(&position, first);
+            }
+            position = Some(*last);
+        }
+    }
+    travel
+}
+
+/// An oriented reference to one of the lines we're ordering.
+#[derive(Clone, Copy, Debug)]
+struct Leg {
+    index: usize,
+    start: Coord<f64>,
+    end: Coord<f64>,
+    reversed: bool,
+}
+
+impl Leg {
+    fn flip(&mut self) {
+        std::mem::swap(&mut self.start, &mut self.end);
+        self.reversed = !self.reversed;
+    }
+}
+
+fn legs_to_lines(lines: &MultiLineString<f64>, legs: &[Leg]) -> MultiLineString<f64> {
+    MultiLineString::new(
+        legs.iter()
+            .map(|leg| {
+                let mut line = lines.0[leg.index].clone();
+                if leg.reversed {
+                    line.0.reverse();
+                }
+                line
+            })
+            .collect(),
+    )
+}
+
+/// Always heads to the closest unvisited line end, flipping the line if we
+/// land on its far end. If the lines aren't reversible, only their starts
+/// are considered.
+fn nearest_neighbour(
+    lines: &MultiLineString<f64>,
+    from: Option<Coord<f64>>,
+    reversible: bool,
+) -> Vec<Leg> {
+    let mut remaining: Vec<Leg> = lines
+        .0
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| match (line.0.first(), line.0.last()) {
+            (Some(start), Some(end)) => Some(Leg {
+                index,
+                start: *start,
+                end: *end,
+                reversed: false,
+            }),
+            _ => None,
+        })
+        .collect();
+    let mut ordered: Vec<Leg> = Vec::with_capacity(remaining.len());
+    let mut position = match from {
+        Some(position) => position,
+        None => match remaining.first() {
+            Some(leg) => leg.start,
+            None => return ordered,
+        },
+    };
+    while !remaining.is_empty() {
+        let mut best = (0usize, f64::MAX, false);
+        for (i, leg) in remaining.iter().enumerate() {
+            let to_start = distance(&position, &leg.start);
+            if to_start < best.1 {
+                best = (i, to_start, false);
+            }
+            let to_end = distance(&position, &leg.end);
+            if reversible && to_end < best.1 {
+                best = (i, to_end, true);
+            }
+        }
+        let mut leg = remaining.swap_remove(best.0);
+        if best.2 {
+            leg.flip();
+        }
+        position = leg.end;
+        ordered.push(leg);
+    }
+    ordered
+}
+
+/// Improves a route by reversing runs of legs whenever that shortens the
+/// travel, until nothing improves or we hit the deadline.
+fn two_opt(legs: &mut [Leg], from: Option<Coord<f64>>, deadline: Instant) {
+    let count = legs.len();
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..count {
+            if Instant::now() > deadline {
+                return;
+            }
+            let before = if i == 0 { from } else { Some(legs[i - 1].end) };
+            for j in (i + 1)..count {
+                let after = legs.get(j + 1).map(|leg| leg.start);
+                let old_cost = before.map_or(0., |b| distance(&b, &legs[i].start))
+                    + after.map_or(0., |a| distance(&legs[j].end, &a));
+                let new_cost = before.map_or(0., |b| distance(&b, &legs[j].end))
+                    + after.map_or(0., |a| distance(&legs[i].start, &a));
+                if new_cost + 1e-9 < old_cost {
+                    legs[i..=j].reverse();
+                    legs[i..=j].iter_mut().for_each(|leg| leg.flip());
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+/// Orders lines for posting with the configured strategy, keeping a
+/// running tally of the pen-up travel before and after.
+pub struct PathPlanner {
+    strategy: PathStrategy,
+    keepdown: f64,
+    deadline: Instant,
+    position: Option<Coord<f64>>,
+    pub travel_before: f64,
+    pub travel_after: f64,
+}
+
+impl PathPlanner {
+    /// The 2-opt time budget is shared by everything planned with this
+    /// planner, so it starts counting now.
+    pub fn new(options: &PostOptions, keepdown: f64) -> Self {
+        Self {
+            strategy: options.path_strategy.clone(),
+            keepdown,
+            deadline: Instant::now() + Duration::from_millis(options.two_opt_budget_ms),
+            position: None,
+            travel_before: 0.,
+            travel_after: 0.,
+        }
+    }
+
+    /// Orders the lines. Lines that aren't reversible keep their direction,
+    /// which rules out the Greedy optimizer and 2-opt (both flip lines), so
+    /// those fall back to nearest neighbour.
+    pub fn plan(&mut self, lines: &MultiLineString<f64>, reversible: bool) -> MultiLineString<f64> {
+        self.travel_before += travel_distance(lines, self.position);
+        let planned = match self.strategy {
+            _ if !reversible => {
+                legs_to_lines(lines, &nearest_neighbour(lines, self.position, false))
+            }
+            PathStrategy::Greedy => {
+                Optimizer::new(self.keepdown, OptimizationStrategy::Greedy).optimize(lines)
+            }
+            PathStrategy::NearestNeighbour => {
+                legs_to_lines(lines, &nearest_neighbour(lines, self.position, true))
+            }
+            PathStrategy::TwoOpt => {
+                let mut legs = nearest_neighbour(lines, self.position, true);
+                two_opt(&mut legs, self.position, self.deadline);
+                legs_to_lines(lines, &legs)
+            }
+        };
+        self.travel_after += travel_distance(&planned, self.position);
+        if let Some(last) = planned.0.iter().rev().find_map(|line| line.0.last()) {
+            self.position = Some(*last);
+        }
+        planned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{LineString, coord};
+
+    fn segment(x0: f64, y0: f64, x1: f64, y1: f64) -> LineString<f64> {
+        LineString::new(vec![coord! {x: x0, y: y0}, coord! {x: x1, y: y1}])
+    }
+
+    #[test]
+    fn test_travel_distance() {
+        let lines = MultiLineString::new(vec![segment(0., 0., 10., 0.), segment(10., 5., 0., 5.)]);
+        assert_eq!(travel_distance(&lines, None), 5.);
+        assert_eq!(travel_distance(&lines, Some(coord! {x: 0., y: -3.})), 8.);
+    }
+
+    #[test]
+    fn test_nearest_neighbour_reverses_lines() {
+        let lines = MultiLineString::new(vec![segment(0., 0., 10., 0.), segment(0., 5., 10., 5.)]);
+        let legs = nearest_neighbour(&lines, None, true);
+        let planned = legs_to_lines(&lines, &legs);
+        assert_eq!(travel_distance(&planned, None), 5.);
+        assert_eq!(planned.0[1].0[0], coord! {x: 10., y: 5.});
+    }
+
+    #[test]
+    fn test_nearest_neighbour_keeps_direction() {
+        let lines = MultiLineString::new(vec![
+            segment(0., 0., 10., 0.),
+            segment(0., 20., 10., 20.),
+            segment(0., 5., 10., 5.),
+        ]);
+        let legs = nearest_neighbour(&lines, None, false);
+        let planned = legs_to_lines(&lines, &legs);
+        assert!(planned.0.iter().all(|line| line.0[0].x == 0.));
+        assert_eq!(planned.0[1].0[0], coord! {x: 0., y: 5.});
+    }
+
+    #[test]
+    fn test_two_opt_never_worse() {
+        let lines = MultiLineString::new(
+            (0..40)
+                .map(|i| {
+                    let x = ((i * 37) % 40) as f64;
+                    let y = ((i * 11) % 13) as f64;
+                    segment(x, y, x + 0.5, y + 0.5)
+                })
+                .collect(),
+        );
+        let mut legs = nearest_neighbour(&lines, None, true);
+        let nn_travel = travel_distance(&legs_to_lines(&lines, &legs), None);
+        two_opt(&mut legs, None, Instant::now() + Duration::from_secs(5));
+        let planned = legs_to_lines(&lines, &legs);
+        assert_eq!(planned.0.len(), 40);
+        assert!(travel_distance(&planned, None) <= nn_travel + 1e-9);
+    }
+
+    #[test]
+    fn test_planner_tallies_travel() {
+        let options = PostOptions {
+            path_strategy: PathStrategy::NearestNeighbour,
+            ..PostOptions::default()
+        };
+        let mut planner = PathPlanner::new(&options, 0.5);
+        let lines = MultiLineString::new(vec![
+            segment(0., 0., 10., 0.),
+            segment(0., 10., 10., 10.),
+            segment(0., 5., 10., 5.),
+        ]);
+        let planned = planner.plan(&lines, true);
+        assert_eq!(planned.0.len(), 3);
+        assert!(planner.travel_after < planner.travel_before);
+    }
+}
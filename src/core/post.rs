@@ -1,21 +1,166 @@
+use std::collections::BTreeSet;
 use std::usize;
 
+use super::arcs::{PathSegment, fit_arcs};
+use super::config::{PathStrategy, PostOptions};
+use super::estimate::estimate_program;
+use super::hpgl::post_hpgl;
+use super::machine::{MachineConfig, MachineVariant, ToolSlot};
+use super::modal::compress_program;
+use super::optimize::PathPlanner;
+use super::post_context::{JobBounds, job_context, set_pen_context};
 use super::project::PenDetail;
 use super::sender::PlotterCommand;
+use super::simplify::simplify_lines;
+use super::validate::{ValidationError, Violation, validate};
 
-use super::project::Project;
+use super::project::{BAPGeometry, KeepdownStrategy, Project};
 use anyhow::Result as AnyResult;
 use anyhow::anyhow;
-use aoer_plotty_rs::optimizer::*;
 use geo::Coord;
 #[allow(deprecated)]
 use geo::EuclideanDistance;
-use geo::{Geometry, LineString, MultiLineString};
+use geo::{Geometry, LineString, MultiLineString, Translate};
 use nalgebra::{Affine2, Matrix3};
-use tera::Context;
+use tera::{Context, Tera};
+use uuid::Uuid;
 
 use super::commands::ApplicationStateChangeMsg;
 
+fn program_bytes(program: &[String]) -> usize {
+    program.iter().map(|line| line.len() + 1).sum()
+}
+
+/// What the post did to the job, so the user can judge whether
+/// the slower options are paying for themselves.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct PostSummary {
+    pub strategy: PathStrategy,
+    /// Pen-up travel (mm) if we drew the lines in their original order.
+    pub travel_before: f64,
+    /// Pen-up travel (mm) after optimization.
+    pub travel_after: f64,
+    /// How many arc moves we fitted.
+    pub arcs: usize,
+    /// How many line moves those arcs replaced.
+    pub arc_segments_replaced: usize,
+    /// How many vertices simplification threw away.
+    pub vertices_removed: usize,
+    /// Bytes of output modal compression saved.
+    pub modal_bytes_saved: usize,
+    /// The program line each pen starts drawing at, the pen's identity,
+    /// and its name for display.
+    pub pen_starts: Vec<(usize, Uuid, String)>,
+    /// The program line the epilog starts at.
+    pub epilog_start: usize,
+    /// Where each tool change starts and ends (exclusive), not counting
+    /// putting the last pen back in its slot.
+    pub tool_changes: Vec<(usize, usize)>,
+    /// The program line each geometry starts at, and its index in the
+    /// project's plot_geometry. Pooled geometry starts with its run.
+    pub geometry_starts: Vec<(usize, usize)>,
+    /// The program lines where the pen travels up to the start of a line.
+    pub stroke_starts: Vec<usize>,
+    /// The program lines where the pen lifts, including the epilog. Moves
+    /// between a drop and the next lift are kept down.
+    pub lifts: Vec<usize>,
+}
+
+impl PostSummary {
+    /// Fills in the travel figures once the planner is done.
+    pub fn record_planner(&mut self, strategy: &PathStrategy, planner: &PathPlanner) {
+        self.strategy = strategy.clone();
+        self.travel_before = planner.travel_before;
+        self.travel_after = planner.travel_after;
+    }
+
+    /// Notes that the given pen starts at this line, unless it's the
+    /// pen we're already drawing with.
+    pub fn record_pen(&mut self, line: usize, pen: &PenDetail) {
+        if self.pen_starts.last().map(|(_, uuid, _)| *uuid) != Some(pen.identity) {
+            self.pen_starts.push((line, pen.identity, pen.name.clone()));
+        }
+    }
+
+    /// Every program line the summary points at, in order.
+    fn marks(&self) -> Vec<usize> {
+        let mut marks: Vec<usize> = self
+            .pen_starts
+            .iter()
+            .map(|(line, _, _)| *line)
+            .chain(
+                self.tool_changes
+                    .iter()
+                    .flat_map(|(start, end)| [*start, *end]),
+            )
+            .chain(self.geometry_starts.iter().map(|(line, _)| *line))
+            .chain(self.stroke_starts.iter().copied())
+            .chain(self.lifts.iter().copied())
+            .chain([self.epilog_start])
+            .collect();
+        marks.sort();
+        marks.dedup();
+        marks
+    }
+
+    /// Moves every program line the summary points at.
+    fn remap_lines(&mut self, remap: impl Fn(usize) -> usize) {
+        for (line, _, _) in self.pen_starts.iter_mut() {
+            *line = remap(*line);
+        }
+        for (start, end) in self.tool_changes.iter_mut() {
+            (*start, *end) = (remap(*start), remap(*end));
+        }
+        for (line, _) in self.geometry_starts.iter_mut() {
+            *line = remap(*line);
+        }
+        for line in self.stroke_starts.iter_mut() {
+            *line = remap(*line);
+        }
+        for line in self.lifts.iter_mut() {
+            *line = remap(*line);
+        }
+        self.epilog_start = remap(self.epilog_start);
+    }
+}
+
+impl std::fmt::Display for PostSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let saved = if self.travel_before > 0. {
+            100. * (self.travel_before - self.travel_after) / self.travel_before
+        } else {
+            0.
+        };
+        write!(
+            f,
+            "{} optimization: pen-up travel {:.0}mm -> {:.0}mm ({:.1}% saved)",
+            self.strategy, self.travel_before, self.travel_after, saved
+        )?;
+        if self.vertices_removed > 0 {
+            write!(
+                f,
+                "\nSimplification removed {} vertices",
+                self.vertices_removed
+            )?;
+        }
+        if self.arcs > 0 {
+            write!(
+                f,
+                "\n{} arcs replaced {} line moves",
+                self.arcs, self.arc_segments_replaced
+            )?;
+        }
+        if self.modal_bytes_saved > 0 {
+            write!(
+                f,
+                "\nModal G-code saved {}kB of output",
+                self.modal_bytes_saved / 1024
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 #[allow(unused)]
 pub enum LastMove {
@@ -27,6 +172,7 @@ pub enum LastMove {
 impl super::ApplicationCore {
     pub fn handle_new_gcode(&mut self, program: &Vec<String>) {
         self.program = Some(program.clone());
+        self.post_summary = None;
         self.project.set_program(Some(Box::new(program.clone())));
         self.state_change_out
             .send(ApplicationStateChangeMsg::PostComplete(
@@ -37,6 +183,7 @@ impl super::ApplicationCore {
         self.gcode =
             Some(gcode::parse(self.program.clone().unwrap().join("\n").as_str()).collect());
 
+        self.sync_plotter_variant();
         let resp = self.plot_sender.send(PlotterCommand::Program(Box::new(
             self.program.as_ref().unwrap().clone(),
         )));
@@ -47,21 +194,45 @@ impl super::ApplicationCore {
         }
     }
 
+    /// Picks the geometry that failed validation, so it shows up in the view.
+    fn pick_violations(&mut self, violations: &[Violation]) {
+        let picked: BTreeSet<u32> = violations.iter().map(|v| v.geometry as u32).collect();
+        self.yolo_app_state_change(ApplicationStateChangeMsg::Picked(Some(
+            picked.iter().map(|i| *i as usize).collect(),
+        )));
+        self.picked = Some(picked);
+    }
+
     pub fn handle_post(&mut self) {
         let pconfig = self.config.post_options.clone();
-        match post(&self.project, pconfig.reorder_by_tool) {
-            Ok(program) => {
+        if let Ok(violations) = validate(&self.project)
+            && !violations.is_empty()
+        {
+            self.pick_violations(&violations);
+            self.yolo_app_state_change(ApplicationStateChangeMsg::PostViolations(violations));
+        }
+        match post(&self.project, &pconfig) {
+            Ok((program, summary)) => {
                 self.handle_new_gcode(&program);
+                if let Some(machine) = self.project.machine() {
+                    self.yolo_app_state_change(ApplicationStateChangeMsg::PlotEstimate(
+                        estimate_program(&program, &machine, &summary.pen_starts, &summary.lifts),
+                    ));
+                }
+                self.post_summary = Some(summary.clone());
+                self.yolo_app_state_change(ApplicationStateChangeMsg::PostSummary(summary));
                 self.yolo_app_state_change(ApplicationStateChangeMsg::GCode(Some(
                     self.program.as_ref().unwrap().join("\n"),
                 )));
                 self.ctx.request_repaint();
             }
             Err(err) => {
+                let msg = match err.downcast_ref::<ValidationError>() {
+                    Some(invalid) => format!("Post refused. {}", invalid),
+                    None => format!("Failed to post due to {:?}.", err),
+                };
                 self.state_change_out
-                    .send(ApplicationStateChangeMsg::Error(
-                        format!("Failed to post due to {:?}.", err).into(),
-                    ))
+                    .send(ApplicationStateChangeMsg::Error(msg))
                     .expect("Failed to send error to viewmodel.");
 
                 self.ctx.request_repaint();
@@ -70,36 +241,20 @@ impl super::ApplicationCore {
     }
 }
 
-pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>> {
-    let machine = project.machine().ok_or(anyhow!("Invalid machine"))?;
-    let post_template = &machine.post_template()?;
-    let mut pen_up = false;
-    let mut distance_down = 0.0f64; // Used to ensure we do an extra pen down periodically?
-    let mut total_points = 0usize;
-    let mut skipped_points = 0usize;
-    // #[allow(unused)]
-    // let mut last_move = LastMove::None;
-
-    let mut program: Vec<String> = Vec::new();
-    program.extend(
-        post_template
-            .render("prelude", &Context::new())?
-            .split("\n")
-            .map(|s| s.to_string()),
-    );
-    if let Some(height) = machine.skim() {
-        let mut context = Context::new();
-        context.insert("skim", &height);
-        program.extend(
-            post_template
-                .render("penup_skim", &context)?
-                .split("\n")
-                .map(|s| s.to_string()),
-        );
-        pen_up = true;
-    }
-    let (mut last_x, mut last_y) = (-9999999., -99999999.);
+/// Builds the affine transform that takes project (screen) coordinates into
+/// machine coordinates, based on the project origin and the machine's home
+/// corner and axis directions.
+pub fn post_transform(project: &Project) -> AnyResult<Affine2<f64>> {
+    let machine = project
+        .machine()
+        .ok_or(anyhow!("Project machine limits are not configured"))?;
+    Ok(machine.bed_to_machine() * bed_transform(project)?)
+}
 
+/// Takes project (screen) coordinates to bed coordinates: mm from the
+/// bottom left corner of the machine's travel, which sits at the project
+/// origin, with Y up.
+pub fn bed_transform(project: &Project) -> AnyResult<Affine2<f64>> {
     let scalex = 1.;
     let scaley = -1.;
 
@@ -113,7 +268,7 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
         return Err(anyhow!("Project extents are not configured"));
     };
 
-    let tx_affine2 = Affine2::<f64>::from_matrix_unchecked(Matrix3::new(
+    Ok(Affine2::<f64>::from_matrix_unchecked(Matrix3::new(
         scalex.clone(),
         0.,
         tx.clone(),
@@ -123,12 +278,17 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
         0.,
         0.,
         1.,
-    ));
+    )))
+}
 
-    let mut last_tool: usize = usize::MAX;
-    let mut working_geo = project.plot_geometry.clone();
+/// Returns the plot geometry in the order it should be drawn, optionally
+/// keeping each tool's operations together. Each comes with its index in
+/// the project's plot_geometry.
+pub fn ordered_geometry(project: &Project, reorder_by_tool: bool) -> Vec<(usize, BAPGeometry)> {
+    let mut working_geo: Vec<(usize, BAPGeometry)> =
+        project.plot_geometry.iter().cloned().enumerate().collect();
     if reorder_by_tool {
-        working_geo.sort_by(|geo1, geo2| {
+        working_geo.sort_by(|(_, geo1), (_, geo2)| {
             let pen1 = project
                 .pen_by_uuid(geo1.pen_uuid)
                 .unwrap_or(PenDetail::default());
@@ -138,58 +298,209 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
             pen1.tool_id.cmp(&pen2.tool_id)
         });
     }
-    for geometry in &working_geo {
-        //&project.plot_geometry {
-        let geo_lines = geometry
-            .transformed(&tx_affine2)
+    working_geo
+}
+
+/// A batch of lines drawn with a single pen, already in machine
+/// coordinates and ready for the path planner.
+#[derive(Clone, Debug)]
+pub struct PenRun {
+    pub pen_uuid: Uuid,
+    /// Indexes of the run's geometry in the project's plot_geometry.
+    pub geometries: Vec<usize>,
+    pub keepdown_strategy: KeepdownStrategy,
+    pub lines: MultiLineString<f64>,
+}
+
+/// Turns the ordered geometry into pen runs. Normally that's one run per
+/// geometry, but with pool_by_pen consecutive geometry on the same pen is
+/// pooled so travel can be optimized across all of it. A pinned geometry
+/// always starts a new run. Pooled runs use the keepdown strategy of their
+/// first geometry.
+pub fn pen_runs(project: &Project, tx: &Affine2<f64>, options: &PostOptions) -> Vec<PenRun> {
+    let mut runs: Vec<PenRun> = Vec::new();
+    for (idx, geometry) in ordered_geometry(project, options.reorder_by_tool) {
+        let mut lines = geometry
+            .transformed(tx)
             .geometry
             .geometry()
             .to_multi_line_strings();
-        let opt = Optimizer::new(
-            machine.keepdown().unwrap_or(1.0),
-            OptimizationStrategy::Greedy,
-        );
-        //let pen = geometry.stroke.clone().unwrap_or(PenDetail::default());
+        if let Some(run) = runs.last_mut()
+            && options.pool_by_pen
+            && !geometry.pinned
+            && run.pen_uuid == geometry.pen_uuid
+        {
+            run.lines.0.append(&mut lines.0);
+            run.geometries.push(idx);
+        } else {
+            runs.push(PenRun {
+                pen_uuid: geometry.pen_uuid,
+                geometries: vec![idx],
+                keepdown_strategy: geometry.keepdown_strategy,
+                lines,
+            });
+        }
+    }
+    runs
+}
+
+pub fn post(project: &Project, options: &PostOptions) -> AnyResult<(Vec<String>, PostSummary)> {
+    let machine = project.machine().ok_or(anyhow!("Invalid machine"))?;
+    if options.refuse_invalid {
+        let violations = validate(project)?;
+        if !violations.is_empty() {
+            return Err(ValidationError { violations }.into());
+        }
+    }
+    if machine.variant() == MachineVariant::HPGL {
+        return post_hpgl(project, options);
+    }
+    let post_template = &machine.post_template()?;
+    let mut pen_up = false;
+    let mut distance_down = 0.0f64; // Used to ensure we do an extra pen down periodically?
+    let mut total_points = 0usize;
+    let mut skipped_points = 0usize;
+    // #[allow(unused)]
+    // let mut last_move = LastMove::None;
+
+    let tx_affine2 = post_transform(project)?;
+    // Drawing directions are about the page, not the machine's axes.
+    let to_bed = machine.bed_to_machine().inverse();
+
+    let mut last_tool: usize = usize::MAX;
+    let mut planner = PathPlanner::new(options, machine.keepdown().unwrap_or(1.0));
+    let mut summary = PostSummary::default();
+    // Only fit arcs if the machine knows how to draw them.
+    let arc_tolerance = options
+        .arc_tolerance
+        .filter(|_| machine.has_post_section("arc_cw") && machine.has_post_section("arc_ccw"));
+    // Plan everything up front, so the templates know the size of the job.
+    let mut planned_runs = Vec::new();
+    for run in pen_runs(project, &tx_affine2, options) {
         let pen = project
-            .pen_by_uuid(geometry.pen_uuid)
+            .pen_by_uuid(run.pen_uuid)
             .unwrap_or(PenDetail::default());
+        let pen_settings = project.pen_post_settings(run.pen_uuid);
+        let geo_lines = planner.plan(
+            &pen_settings.orient(
+                &simplify_run(project, &run, &pen, options, &mut summary),
+                &to_bed,
+            ),
+            pen_settings.reversible(),
+        );
+        let geo_lines = offset_for_tool(&geo_lines, &machine, pen.tool_id);
+        planned_runs.push((run, pen, pen_settings, geo_lines));
+    }
+    let line_count: usize = planned_runs
+        .iter()
+        .map(|(_, _, pen_settings, geo_lines)| geo_lines.0.len() * pen_settings.passes.max(1))
+        .sum();
+    let bounds = JobBounds::of(planned_runs.iter().map(|(_, _, _, geo_lines)| geo_lines));
+    let mut shared_context = job_context(project, line_count, &bounds);
+    if let Some((run, pen, _, _)) = planned_runs.first() {
+        set_pen_context(
+            &mut shared_context,
+            project,
+            pen,
+            run.geometries.first().copied(),
+        );
+    }
+    let pen_z = machine.pen_z();
+    set_z(&mut shared_context, pen_z.as_ref().map(|z| z.travel));
+
+    let mut program: Vec<String> = Vec::new();
+    program.extend(
+        post_template
+            .render("prelude", &shared_context)?
+            .split("\n")
+            .map(|s| s.to_string()),
+    );
+    if let Some(height) = machine.skim() {
+        set_z(&mut shared_context, pen_z.as_ref().map(|z| z.skim));
+        let mut context = shared_context.clone();
+        context.insert("skim", &height);
+        summary.lifts.push(program.len());
+        program.extend(
+            post_template
+                .render("penup_skim", &context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        );
+        pen_up = true;
+    }
+    let (mut last_x, mut last_y) = (-9999999., -99999999.);
+
+    for (run, pen, pen_settings, geo_lines) in planned_runs {
+        //&project.plot_geometry {
+        //let pen = geometry.stroke.clone().unwrap_or(PenDetail::default());
         // println!("Geo with pen id {}", pen.tool_id);
         let feedrate = pen.feed_rate.unwrap_or(machine.feedrate());
-        let geo_lines = opt.optimize(&geo_lines);
+        set_pen_context(
+            &mut shared_context,
+            project,
+            &pen,
+            run.geometries.first().copied(),
+        );
+        let contact_z = pen_z.as_ref().map(|z| z.contact_for(pen.stroke_density));
+        summary.record_pen(program.len(), &pen);
         if pen.tool_id != last_tool {
             // println!("Emitting tool change.");
-            last_tool = pen.tool_id;
-            let mut context = Context::new();
-            context.insert("tool_id", &pen.tool_id);
+            set_z(&mut shared_context, pen_z.as_ref().map(|z| z.travel));
+            summary.lifts.push(program.len());
             program.extend(
                 post_template
-                    .render("penup", &context)?
+                    .render("penup", &shared_context)?
                     .split("\n")
                     .map(|s| s.to_string()),
             );
             pen_up = true;
-            program.extend(
-                post_template
-                    .render("toolchange", &context)?
-                    .split("\n")
-                    .map(|s| s.to_string()),
-            );
+            if let Some(slot) = machine.tool_slot(last_tool) {
+                program.extend(slot_routine(
+                    post_template,
+                    &shared_context,
+                    &slot,
+                    &slot.release,
+                    machine.feedrate(),
+                )?);
+            }
+            let change_start = program.len();
+            program.extend(tool_change(
+                post_template,
+                &shared_context,
+                &machine,
+                pen.tool_id,
+            )?);
+            summary.tool_changes.push((change_start, program.len()));
+            last_tool = pen.tool_id;
         }
-        for line in geo_lines {
-            let mut context = Context::new();
-            if let Some(height) = machine.skim() {
-                context.insert("skim", &height);
-            };
-
+        for idx in &run.geometries {
+            summary.geometry_starts.push((program.len(), *idx));
+        }
+        let lift_between_passes = pen_settings.lift_between_passes;
+        let passes = geo_lines.0.iter().flat_map(|line| {
+            pen_settings
+                .pass_lines(line)
+                .into_iter()
+                .enumerate()
+                .map(move |(pass, line)| (line, pass > 0 && lift_between_passes))
+        });
+        for (line, force_lift) in passes {
             // TODO: This should definitely be using the keepdown strategy in the project..
             if machine.keepdown().is_some()
                 && !pen_up
+                && !force_lift
                 && ((&line[0].x - last_x).powi(2) + (&line[0].y - last_y).powi(2)).sqrt()
                     < machine.keepdown().unwrap()
             {
                 // Then we're doing a keepdown.
             } else if !pen_up {
                 distance_down = 0.0;
+                set_z(&mut shared_context, pen_z.as_ref().map(|z| z.skim));
+                let mut context = shared_context.clone();
+                if let Some(height) = machine.skim() {
+                    context.insert("skim", &height);
+                };
+                summary.lifts.push(program.len());
                 program.extend(
                     post_template
                         .render("penup_skim", &context)?
@@ -199,7 +510,10 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
                 pen_up = true;
             }
 
-            let mut context = Context::new();
+            if pen_up {
+                summary.stroke_starts.push(program.len());
+            }
+            let mut context = shared_context.clone();
             context.insert("xmm", &line[0].x);
             context.insert("ymm", &line[0].y);
             program.extend(
@@ -223,8 +537,9 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
 
             // TODO: This should definitely be used further down.
             let keepdown = ((&line[0].x - last_x).powi(2) + (&line[0].y - last_y).powi(2)).sqrt()
-                < geometry.keepdown_strategy.threshold(pen_width);
+                < run.keepdown_strategy.threshold(pen_width);
             if !keepdown || pen_up {
+                set_z(&mut shared_context, contact_z);
                 program.extend(
                     post_template
                         .render(
@@ -233,7 +548,7 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
                             } else {
                                 "pendown"
                             },
-                            &Context::new(),
+                            &shared_context,
                         )?
                         .split("\n")
                         .map(|s| s.to_string()),
@@ -241,7 +556,70 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
                 pen_up = false;
             }
 
-            for point in &line.0[1..] {
+            let segments = match arc_tolerance {
+                Some(tolerance) => fit_arcs(&line, tolerance),
+                None => line.0[1..]
+                    .iter()
+                    .map(|point| PathSegment::Line(*point))
+                    .collect(),
+            };
+            let mut segment_start = line.0[0];
+            for segment in segments {
+                let from = segment_start;
+                segment_start = segment.end();
+                let point = match segment {
+                    PathSegment::Line(point) => point,
+                    PathSegment::Arc {
+                        end,
+                        center,
+                        clockwise,
+                        segments: replaced,
+                    } => {
+                        total_points += replaced;
+                        summary.arcs += 1;
+                        summary.arc_segments_replaced += replaced;
+                        // Arcs are relative to where the pen is, so make sure it
+                        // is actually at the start of the arc.
+                        if (last_x, last_y) != (from.x, from.y) {
+                            let mut context = shared_context.clone();
+                            context.insert("xmm", &from.x);
+                            context.insert("ymm", &from.y);
+                            context.insert("feedrate", &feedrate);
+                            program.extend(
+                                post_template
+                                    .render("lineto", &context)?
+                                    .split("\n")
+                                    .map(|s| s.to_string()),
+                            );
+                        }
+                        let start = posted(&from);
+                        let mut context = shared_context.clone();
+                        context.insert("xmm", &end.x);
+                        context.insert("ymm", &end.y);
+                        context.insert("imm", &(center.x - start.x));
+                        context.insert("jmm", &(center.y - start.y));
+                        context.insert("rmm", &center_distance(&center, &start));
+                        context.insert("feedrate", &feedrate);
+                        program.extend(
+                            post_template
+                                .render(if clockwise { "arc_cw" } else { "arc_ccw" }, &context)?
+                                .split("\n")
+                                .map(|s| s.to_string()),
+                        );
+                        distance_down += arc_length(&from, &end, &center, clockwise);
+                        (last_x, last_y) = (end.x, end.y);
+                        if !pen_up && distance_down > 1500.0 {
+                            distance_down = 0.;
+                            program.extend(
+                                post_template
+                                    .render("pendrop", &shared_context)?
+                                    .split("\n")
+                                    .map(|s| s.to_string()),
+                            );
+                        }
+                        continue;
+                    }
+                };
                 // println!("Start of points in line.");
                 total_points += 1;
                 #[allow(deprecated)]
@@ -266,7 +644,7 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
                 }
 
                 (last_x, last_y) = (point.x.clone(), point.y.clone());
-                let mut context = Context::new();
+                let mut context = shared_context.clone();
                 context.insert("xmm", &point.x);
                 context.insert("ymm", &point.y);
                 // context.insert("feedrate", &machine.feedrate());
@@ -292,7 +670,7 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
                     distance_down = 0.;
                     program.extend(
                         post_template
-                            .render("pendrop", &Context::new())?
+                            .render("pendrop", &shared_context)?
                             .split("\n")
                             .map(|s| s.to_string()),
                     );
@@ -300,22 +678,193 @@ pub fn post(project: &Project, reorder_by_tool: bool) -> AnyResult<Vec<String>>
             }
         }
     }
+    set_z(&mut shared_context, pen_z.as_ref().map(|z| z.travel));
+    // Leave the changer full at the end of the job.
+    if let Some(slot) = machine.tool_slot(last_tool) {
+        summary.lifts.push(program.len());
+        program.extend(
+            post_template
+                .render("penup", &shared_context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        );
+        program.extend(slot_routine(
+            post_template,
+            &shared_context,
+            &slot,
+            &slot.release,
+            machine.feedrate(),
+        )?);
+    }
+    summary.epilog_start = program.len();
+    summary.lifts.push(program.len());
     program.extend(
         post_template
-            .render("epilog", &Context::new())?
+            .render("epilog", &shared_context)?
             .split("\n")
             .map(|s| s.to_string()),
     );
     eprintln!(
         "Geometry yielded {} total points, of which we skipped {}% ({} points)",
         total_points,
-        (100 * skipped_points) / total_points,
+        (100 * skipped_points) / total_points.max(1),
         skipped_points,
     );
 
+    summary.record_planner(&options.path_strategy, &planner);
+    if machine.modal() {
+        // Every mark stands on its own, so a plot can be resumed from it.
+        let marks = summary.marks();
+        let mut moved = marks.clone();
+        let compressed = compress_program(&program, &mut moved);
+        summary.remap_lines(|line| {
+            marks
+                .binary_search(&line)
+                .map(|idx| moved[idx])
+                .unwrap_or(line)
+        });
+        summary.modal_bytes_saved = program_bytes(&program) - program_bytes(&compressed);
+        program = compressed;
+    }
+    Ok((program, summary))
+}
+
+/// Picks the next pen up from its slot. A pen without a slot goes through
+/// the toolchange template instead, which usually pauses for the operator.
+fn tool_change(
+    post_template: &Tera,
+    context: &Context,
+    machine: &MachineConfig,
+    to_tool: usize,
+) -> AnyResult<Vec<String>> {
+    let mut program = Vec::new();
+    match machine.tool_slot(to_tool) {
+        Some(slot) => program.extend(slot_routine(
+            post_template,
+            context,
+            &slot,
+            &slot.grab,
+            machine.feedrate(),
+        )?),
+        None => program.extend(
+            post_template
+                .render("toolchange", context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        ),
+    }
     Ok(program)
 }
 
+/// Travels to a slot's approach point, feeds into the slot, runs the grab or
+/// release sequence, and backs out again.
+pub fn slot_routine(
+    post_template: &Tera,
+    context: &Context,
+    slot: &ToolSlot,
+    sequence: &str,
+    feedrate: f64,
+) -> AnyResult<Vec<String>> {
+    let mut context = context.clone();
+    context.insert("tool_id", &slot.tool_id);
+    context.insert("feedrate", &feedrate);
+    let (x, y) = slot.position;
+    let approach = (x + slot.approach.0, y + slot.approach.1);
+    let mut program = Vec::new();
+    for (section, (xmm, ymm)) in [("moveto", approach), ("lineto", slot.position)] {
+        context.insert("xmm", &xmm);
+        context.insert("ymm", &ymm);
+        program.extend(
+            post_template
+                .render(section, &context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        );
+    }
+    program.extend(
+        Tera::one_off(sequence, &context, false)?
+            .split("\n")
+            .map(|s| s.to_string()),
+    );
+    context.insert("xmm", &approach.0);
+    context.insert("ymm", &approach.1);
+    program.extend(
+        post_template
+            .render("lineto", &context)?
+            .split("\n")
+            .map(|s| s.to_string()),
+    );
+    Ok(program)
+}
+
+/// For machines with a Z axis, sets `zmm` to the height the next section
+/// moves the pen to. Moves after it see that as the current height.
+fn set_z(context: &mut Context, height: Option<f64>) {
+    if let Some(height) = height {
+        context.insert("zmm", &height);
+    }
+}
+
+/// Where a point ends up once the templates have rounded it to a thousandth
+/// of a mm. Arc centers are relative to this, rather than the exact start, so
+/// the controller sees the same radius at both ends.
+fn posted(point: &Coord<f64>) -> Coord<f64> {
+    Coord {
+        x: (point.x * 1000.).round() / 1000.,
+        y: (point.y * 1000.).round() / 1000.,
+    }
+}
+
+fn center_distance(center: &Coord<f64>, point: &Coord<f64>) -> f64 {
+    ((point.x - center.x).powi(2) + (point.y - center.y).powi(2)).sqrt()
+}
+
+/// Length of the arc from start to end around center, going the given way.
+pub(crate) fn arc_length(
+    start: &Coord<f64>,
+    end: &Coord<f64>,
+    center: &Coord<f64>,
+    clockwise: bool,
+) -> f64 {
+    let start_angle = (start.y - center.y).atan2(start.x - center.x);
+    let end_angle = (end.y - center.y).atan2(end.x - center.x);
+    let sweep = if clockwise {
+        start_angle - end_angle
+    } else {
+        end_angle - start_angle
+    }
+    .rem_euclid(2. * std::f64::consts::PI);
+    center_distance(center, start) * sweep
+}
+
+/// Moves lines so the given tool, rather than the carriage's reference
+/// point, lands on them.
+pub fn offset_for_tool(
+    lines: &MultiLineString<f64>,
+    machine: &MachineConfig,
+    tool_id: usize,
+) -> MultiLineString<f64> {
+    let (dx, dy) = machine.tool_offset(tool_id);
+    lines.translate(-dx, -dy)
+}
+
+/// Simplifies a run's lines using the post options, with the pen's own
+/// tolerance if it has one, and tallies what was removed.
+pub fn simplify_run(
+    project: &Project,
+    run: &PenRun,
+    pen: &PenDetail,
+    options: &PostOptions,
+    summary: &mut PostSummary,
+) -> MultiLineString<f64> {
+    let tolerance = project
+        .pen_post_settings(run.pen_uuid)
+        .simplify_tolerance(pen, options.simplify_tolerance);
+    let (lines, removed) = simplify_lines(&run.lines, &options.simplify, tolerance);
+    summary.vertices_removed += removed;
+    lines
+}
+
 /// Converts geometry into MultiLineString
 /// Lines/MultiLine are just passed through, whereas polygons
 /// and rects are converted into their Perimeters
@@ -397,4 +946,226 @@ mod tests {
 
     #[test]
     fn test_post() {}
+
+    fn pen_runs_project(pinned: bool) -> Project {
+        let mut project = Project::new();
+        project.set_machine(Some(crate::core::machine::MachineConfig::default()));
+        project.set_origin(&Some((0., 100.)));
+        let pen = PenDetail::default();
+        project.pens.push(pen.clone());
+        for (idx, y) in [10., 20.].iter().enumerate() {
+            project.plot_geometry.push(BAPGeometry {
+                pen_uuid: pen.identity,
+                name: format!("geometry {}", idx),
+                geometry: crate::core::project::GeometryKind::Stroke(Geometry::LineString(
+                    LineString::new(vec![Coord { x: 0., y: *y }, Coord { x: 10., y: *y }]),
+                )),
+                keepdown_strategy: KeepdownStrategy::None,
+                pinned: idx == 1 && pinned,
+                marks: false,
+            });
+        }
+        project
+    }
+
+    #[test]
+    fn test_pen_z() {
+        use crate::core::machine::{MachineConfig, PenZ};
+        let mut project = pen_runs_project(false);
+        project.pens[0].stroke_density = 0.5;
+        let mut machine = MachineConfig::default();
+        machine.set_post_template(&MachineConfig::z_axis_templates());
+        machine.set_pen_z(Some(PenZ {
+            travel: 5.5,
+            skim: 1.5,
+            contact: 0.,
+            pressure: Some(0.5),
+        }));
+        project.set_machine(Some(machine));
+        let (program, _summary) = post(&project, &PostOptions::default()).expect("Failed to post");
+        assert!(program.contains(&"G1 F600 Z-0.25 ; PENDOWN SKIM".to_string()));
+        assert!(program.contains(&"G0 Z1.5 ; PENUP_SKIM".to_string()));
+        assert_eq!(program[program.len() - 3], "G0 Z5.5");
+        // GRBL takes every line of it.
+        use crate::core::sender::PlotterTransport;
+        use crate::core::sim::{SimDialect, SimTransport};
+        let mut sim = SimTransport::new(SimDialect::Grbl);
+        for line in &program {
+            sim.write_line(line).expect("Failed to send");
+        }
+        let mut response = String::new();
+        while sim.read_line(&mut response).is_ok() {
+            assert!(!response.starts_with("error"), "{}", response);
+            response.clear();
+        }
+    }
+
+    #[test]
+    fn test_post_transform_axes() {
+        use crate::core::machine::{MachineAxes, OriginCorner};
+        use nalgebra::Point2;
+        let mut project = pen_runs_project(false);
+        let mut machine = project.machine().unwrap();
+        machine.set_limits((200., 100.));
+        let to_machine = |project: &Project, x: f64, y: f64| {
+            let point = post_transform(project)
+                .unwrap()
+                .transform_point(&Point2::new(x, y));
+            ((point.x * 1e6).round() / 1e6, (point.y * 1e6).round() / 1e6)
+        };
+        // The origin is the bed's bottom left, at (0, 100) in the project.
+        project.set_machine(Some(machine.clone()));
+        assert_eq!(to_machine(&project, 10., 80.), (10., 20.));
+        machine.set_axes(MachineAxes {
+            origin_corner: OriginCorner::TopRight,
+            invert_x: true,
+            ..MachineAxes::default()
+        });
+        project.set_machine(Some(machine.clone()));
+        assert_eq!(to_machine(&project, 10., 80.), (-190., 80.));
+        machine.set_axes(MachineAxes {
+            swap_xy: true,
+            ..MachineAxes::default()
+        });
+        project.set_machine(Some(machine));
+        assert_eq!(to_machine(&project, 10., 80.), (20., 10.));
+    }
+
+    #[test]
+    fn test_tool_offsets() {
+        let mut project = pen_runs_project(false);
+        let mut machine = project.machine().unwrap();
+        let tool_id = project.pens[0].tool_id;
+        machine.set_tool_offsets([(tool_id, (2.5, -1.5))].into_iter().collect());
+        project.set_machine(Some(machine));
+        let options = PostOptions {
+            path_strategy: PathStrategy::NearestNeighbour,
+            ..PostOptions::default()
+        };
+        let (program, _summary) = post(&project, &options).expect("Failed to post");
+        // The first line runs from (0, 90) to (10, 90) on the machine.
+        assert!(program.contains(&"G0 X-2.5 Y91.5 ; NEW LINE START".to_string()));
+        assert!(program.iter().any(|line| line.ends_with("X7.5 Y91.5")));
+    }
+
+    #[test]
+    fn test_tool_slots() {
+        use crate::core::machine::ToolSlot;
+        let mut project = pen_runs_project(false);
+        let first_tool = project.pens[0].tool_id;
+        let pen = PenDetail {
+            identity: Uuid::new_v4(),
+            tool_id: first_tool + 1,
+            ..PenDetail::default()
+        };
+        project.plot_geometry[1].pen_uuid = pen.identity;
+        project.pens.push(pen);
+        let mut machine = project.machine().unwrap();
+        machine.set_tool_slots(
+            (0..2)
+                .map(|slot| ToolSlot {
+                    tool_id: first_tool + slot,
+                    position: (200., 20. * slot as f64),
+                    approach: (-10., 0.),
+                    grab: "M5 ; GRAB T{{tool_id}}".to_string(),
+                    release: "M3 ; RELEASE T{{tool_id}}".to_string(),
+                })
+                .collect(),
+        );
+        project.set_machine(Some(machine));
+        let (program, _summary) = post(&project, &PostOptions::default()).expect("Failed to post");
+        let changes: Vec<&String> = program
+            .iter()
+            .filter(|line| line.contains("GRAB") || line.contains("RELEASE"))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                &format!("M5 ; GRAB T{}", first_tool),
+                &format!("M3 ; RELEASE T{}", first_tool),
+                &format!("M5 ; GRAB T{}", first_tool + 1),
+                &format!("M3 ; RELEASE T{}", first_tool + 1),
+            ]
+        );
+        assert!(!program.iter().any(|line| line.starts_with("$M06")));
+    }
+
+    #[test]
+    fn test_arc_radius_agrees() {
+        use crate::core::modal::parse_words;
+        let mut project = pen_runs_project(false);
+        let (cx, cy, radius) = (50.3337, 40.7771, 10.1234);
+        project.plot_geometry[0].geometry =
+            crate::core::project::GeometryKind::Stroke(Geometry::LineString(LineString::new(
+                (0..=48)
+                    .map(|idx| {
+                        let angle = std::f64::consts::PI * idx as f64 / 48.;
+                        Coord {
+                            x: cx + radius * angle.cos(),
+                            y: cy + radius * angle.sin(),
+                        }
+                    })
+                    .collect(),
+            )));
+        project.plot_geometry.truncate(1);
+        let options = PostOptions {
+            arc_tolerance: Some(0.01),
+            ..PostOptions::default()
+        };
+        let (program, summary) = post(&project, &options).expect("Failed to post");
+        assert!(summary.arcs > 0);
+        // Read the program back like GRBL would, and check the radius from
+        // the posted start and end to the posted center agree.
+        let (mut x, mut y) = (0., 0.);
+        for line in &program {
+            let words = parse_words(line);
+            let word = |letter: char| {
+                words
+                    .iter()
+                    .find(|(found, value)| *found == letter && !value.is_nan())
+                    .map(|(_, value)| *value)
+            };
+            let arc = word('G').is_some_and(|code| code == 2. || code == 3.);
+            let (x1, y1) = (word('X').unwrap_or(x), word('Y').unwrap_or(y));
+            if arc {
+                let (i, j) = (word('I').unwrap(), word('J').unwrap());
+                let start = (i * i + j * j).sqrt();
+                let end = ((x + i - x1).powi(2) + (y + j - y1).powi(2)).sqrt();
+                assert!(
+                    (start - end).abs() < 0.005,
+                    "{}: {} vs {}",
+                    line,
+                    start,
+                    end
+                );
+            }
+            (x, y) = (x1, y1);
+        }
+    }
+
+    #[test]
+    fn test_arc_length() {
+        let center = Coord { x: 0., y: 0. };
+        let start = Coord { x: 1., y: 0. };
+        let end = Coord { x: 0., y: 1. };
+        let quarter = std::f64::consts::FRAC_PI_2;
+        assert!((arc_length(&start, &end, &center, false) - quarter).abs() < 1e-9);
+        assert!((arc_length(&start, &end, &center, true) - 3. * quarter).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pen_runs_pooling() {
+        let project = pen_runs_project(false);
+        let tx = post_transform(&project).unwrap();
+        let mut options = PostOptions::default();
+        assert_eq!(pen_runs(&project, &tx, &options).len(), 2);
+        options.pool_by_pen = true;
+        let runs = pen_runs(&project, &tx, &options);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].lines.0.len(), 2);
+        assert_eq!(runs[0].geometries, vec![0, 1]);
+
+        let project = pen_runs_project(true);
+        assert_eq!(pen_runs(&project, &tx, &options).len(), 2);
+    }
 }
@@ -0,0 +1,136 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use geo::MultiLineString;
+use serde::Serialize;
+use tera::Context;
+
+use super::project::{PenDetail, Project};
+
+/// The extents of the job, in machine coordinates.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct JobBounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl JobBounds {
+    /// Bounds of all the lines, or all zeroes if there aren't any.
+    pub fn of<'a>(lines: impl IntoIterator<Item = &'a MultiLineString<f64>>) -> Self {
+        let mut bounds: Option<(f64, f64, f64, f64)> = None;
+        for point in lines
+            .into_iter()
+            .flat_map(|lines| lines.0.iter())
+            .flat_map(|line| line.0.iter())
+        {
+            bounds = Some(match bounds {
+                Some((min_x, min_y, max_x, max_y)) => (
+                    min_x.min(point.x),
+                    min_y.min(point.y),
+                    max_x.max(point.x),
+                    max_y.max(point.y),
+                ),
+                None => (point.x, point.y, point.x, point.y),
+            });
+        }
+        match bounds {
+            Some((min_x, min_y, max_x, max_y)) => Self {
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+                width: max_x - min_x,
+                height: max_y - min_y,
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// The context every post template section starts from. Sections add their
+/// own values (xmm, ymm, feedrate and so on) on top of a clone of it.
+///
+/// Job-wide: `project_file`, `timestamp` (unix seconds, so it works with
+/// Tera's `date` filter), `paper_width`, `paper_height`, `line_count` and
+/// `bounds` (`min_x`, `min_y`, `max_x`, `max_y`, `width`, `height`).
+pub fn job_context(project: &Project, line_count: usize, bounds: &JobBounds) -> Context {
+    let mut context = Context::new();
+    let project_file = project
+        .file_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or("untitled".to_string());
+    context.insert("project_file", &project_file);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    context.insert("timestamp", &timestamp);
+    let (paper_width, paper_height) = project.paper.oriented_dimensions();
+    context.insert("paper_width", &paper_width);
+    context.insert("paper_height", &paper_height);
+    context.insert("line_count", &line_count);
+    context.insert("bounds", bounds);
+    set_pen_context(&mut context, project, &PenDetail::default(), None);
+    context
+}
+
+/// Updates the pen and geometry being drawn: `tool_id`, `pen_name`,
+/// `pen_color` (#rrggbb), `pen_width`, `geometry_index` and `geometry_name`.
+pub fn set_pen_context(
+    context: &mut Context,
+    project: &Project,
+    pen: &PenDetail,
+    geometry: Option<usize>,
+) {
+    let [r, g, b, _a] = pen.color.to_rgba8();
+    context.insert("tool_id", &pen.tool_id);
+    context.insert("pen_name", &pen.name);
+    context.insert("pen_color", &format!("#{:02x}{:02x}{:02x}", r, g, b));
+    context.insert("pen_width", &pen.stroke_width);
+    context.insert("geometry_index", &geometry.unwrap_or(0));
+    context.insert(
+        "geometry_name",
+        &geometry
+            .and_then(|idx| project.plot_geometry.get(idx))
+            .map(|geometry| geometry.name.clone())
+            .unwrap_or_default(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{LineString, coord};
+    use tera::Tera;
+
+    #[test]
+    fn test_job_context() {
+        let lines = MultiLineString::new(vec![LineString::new(vec![
+            coord! {x: 10.5, y: 20.5},
+            coord! {x: 40.5, y: 5.5},
+        ])]);
+        let bounds = JobBounds::of([&lines]);
+        assert_eq!(bounds.width, 30.);
+        assert_eq!(bounds.height, 15.);
+        let project = Project::new();
+        let mut context = job_context(&project, 1, &bounds);
+        let pen = PenDetail {
+            name: "Fineliner".to_string(),
+            color: csscolorparser::Color::from_rgba8(255, 0, 16, 255),
+            ..PenDetail::default()
+        };
+        set_pen_context(&mut context, &project, &pen, None);
+        let rendered = Tera::one_off(
+            "{{pen_name}} {{pen_color}} {{line_count}} {{bounds.min_x}},{{bounds.max_y}} {{project_file}}",
+            &context,
+            false,
+        )
+        .expect("Failed to render");
+        assert_eq!(rendered, "Fineliner #ff0010 1 10.5,20.5 untitled");
+    }
+}
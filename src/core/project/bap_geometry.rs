@@ -15,6 +15,14 @@ pub struct BAPGeometry {
     pub name: String,
     pub geometry: GeometryKind,
     pub keepdown_strategy: KeepdownStrategy,
+    /// Pinned geometry is never pooled with the geometry before it when
+    /// optimizing travel, so it always gets drawn after everything above it.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Crop and registration marks generated from the project's marks
+    /// config, which are replaced wholesale whenever they're redrawn.
+    #[serde(default)]
+    pub marks: bool,
 }
 
 impl BAPGeometry {
@@ -24,6 +32,8 @@ impl BAPGeometry {
             geometry: self.geometry.transformed(tx),
             keepdown_strategy: self.keepdown_strategy,
             name: self.name.clone(),
+            pinned: self.pinned,
+            marks: self.marks,
         }
     }
 
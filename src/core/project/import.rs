@@ -157,6 +157,8 @@ impl super::Project {
                         None => Uuid::new_v4(),
                     },
                     keepdown_strategy: geometry.keepdown_strategy,
+                    pinned: false,
+                    marks: false,
                 });
             }
             if import_pens {
@@ -200,6 +202,8 @@ impl super::Project {
                             pen_uuid: tmp_id,
                             geometry: GeometryKind::Stroke(geo.geometry.clone()),
                             keepdown_strategy: geo.keepdown_strategy,
+                            pinned: false,
+                            marks: false,
                         }
                     })
                     .collect();
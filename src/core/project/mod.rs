@@ -1,3 +1,4 @@
+use crate::core::commands::MarksConfig;
 use crate::core::machine::MachineConfig;
 use anyhow::{Result, anyhow};
 use aoer_plotty_rs::context::operation::OPLayer;
@@ -22,11 +23,13 @@ pub(crate) mod bap_geometry;
 pub(crate) mod extents;
 pub(crate) mod geometry_kind;
 pub(crate) mod import;
+pub(crate) mod pen_post;
 // pub(crate) mod project;
 pub(crate) mod reorder;
 pub(crate) mod transforms;
 pub use bap_geometry::BAPGeometry;
 pub use geometry_kind::GeometryKind;
+pub use pen_post::{DrawDirection, PenPostSettings};
 // pub use project::*;
 
 #[allow(unused)]
@@ -65,6 +68,10 @@ pub struct Project {
     program: Option<Box<Vec<String>>>,
     pub do_keepdown: bool,
     pub file_path: Option<PathBuf>,
+    #[serde(default)]
+    pub pen_post: HashMap<Uuid, PenPostSettings>,
+    #[serde(default)]
+    pub marks: Option<MarksConfig>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -93,6 +100,8 @@ impl Project {
             program: None,
             do_keepdown: true,
             file_path: None,
+            pen_post: HashMap::new(),
+            marks: None,
         }
     }
 
@@ -160,6 +169,11 @@ impl Project {
         None
     }
 
+    /// Post settings for the given pen, or the defaults if it has none.
+    pub fn pen_post_settings(&self, uuid: Uuid) -> PenPostSettings {
+        self.pen_post.get(&uuid).cloned().unwrap_or_default()
+    }
+
     pub fn save(&self) -> Result<PathBuf> {
         match &self.file_path {
             Some(path) => Ok(self.save_to_path(path)?),
@@ -302,6 +316,8 @@ impl Project {
                         name: format!("geometry {}", idx).to_string(),
                         geometry: GeometryKind::Stroke(old_geo.geometry.clone()),
                         keepdown_strategy: old_geo.keepdown_strategy,
+                        pinned: false,
+                        marks: false,
                     }
                 })
             }
@@ -0,0 +1,221 @@
+use geo::{Coord, LineString, MultiLineString};
+use nalgebra::{Affine2, Point2};
+use serde::{Deserialize, Serialize};
+
+use super::PenDetail;
+
+/// Which way a pen is allowed to draw open paths. Brush and ruling pens
+/// smear when they're dragged backwards.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum DrawDirection {
+    /// The optimizer may flip lines however it likes.
+    #[default]
+    Any,
+    /// Lines are drawn the way they were created.
+    AsDrawn,
+    LeftToRight,
+    TopToBottom,
+}
+
+impl DrawDirection {
+    pub fn all() -> Vec<DrawDirection> {
+        vec![
+            DrawDirection::Any,
+            DrawDirection::AsDrawn,
+            DrawDirection::LeftToRight,
+            DrawDirection::TopToBottom,
+        ]
+    }
+
+    /// Flips open lines to suit the direction. Lines are in machine
+    /// coordinates, and to_bed takes them back to the bed (Y up), since
+    /// the machine's axes may be swapped or inverted.
+    pub fn orient(&self, line: &LineString<f64>, to_bed: &Affine2<f64>) -> LineString<f64> {
+        let mut line = line.clone();
+        if line.is_closed() {
+            return line;
+        }
+        if let (Some(first), Some(last)) = (line.0.first(), line.0.last()) {
+            let first = to_bed.transform_point(&Point2::new(first.x, first.y));
+            let last = to_bed.transform_point(&Point2::new(last.x, last.y));
+            let backwards = match self {
+                DrawDirection::Any | DrawDirection::AsDrawn => false,
+                DrawDirection::LeftToRight => first.x > last.x,
+                DrawDirection::TopToBottom => first.y < last.y,
+            };
+            if backwards {
+                line.0.reverse();
+            }
+        }
+        line
+    }
+}
+
+impl std::fmt::Display for DrawDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrawDirection::Any => write!(f, "Any"),
+            DrawDirection::AsDrawn => write!(f, "As drawn"),
+            DrawDirection::LeftToRight => write!(f, "Left to right"),
+            DrawDirection::TopToBottom => write!(f, "Top to bottom"),
+        }
+    }
+}
+
+/// Per-pen post settings. PenDetail lives in the plotty crate, so the
+/// settings that only matter to our post are kept alongside it in the
+/// project, keyed by the pen's identity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PenPostSettings {
+    /// Simplify this pen's lines to a fraction of its stroke width,
+    /// instead of the global simplification tolerance.
+    #[serde(default)]
+    pub simplify_width_fraction: Option<f64>,
+    /// How many times to draw each line, for pens that need a few coats.
+    #[serde(default = "default_passes")]
+    pub passes: usize,
+    /// Each extra pass is nudged this far (mm) diagonally from the last.
+    #[serde(default)]
+    pub pass_offset: f64,
+    /// Draw every other pass backwards, so the pen can stay down.
+    #[serde(default)]
+    pub reverse_passes: bool,
+    /// Always lift the pen between passes, even if it could stay down.
+    #[serde(default)]
+    pub lift_between_passes: bool,
+    #[serde(default)]
+    pub direction: DrawDirection,
+}
+
+fn default_passes() -> usize {
+    1
+}
+
+impl Default for PenPostSettings {
+    fn default() -> Self {
+        Self {
+            simplify_width_fraction: None,
+            passes: default_passes(),
+            pass_offset: 0.,
+            reverse_passes: false,
+            lift_between_passes: false,
+            direction: DrawDirection::default(),
+        }
+    }
+}
+
+impl PenPostSettings {
+    /// The simplification tolerance (mm) to use for this pen.
+    pub fn simplify_tolerance(&self, pen: &PenDetail, default: f64) -> f64 {
+        match self.simplify_width_fraction {
+            Some(fraction) => pen.stroke_width * fraction,
+            None => default,
+        }
+    }
+
+    /// Whether the optimizer may flip this pen's lines.
+    pub fn reversible(&self) -> bool {
+        self.direction == DrawDirection::Any
+    }
+
+    /// The lines flipped to this pen's drawing direction, ready to plan.
+    /// See DrawDirection::orient.
+    pub fn orient(
+        &self,
+        lines: &MultiLineString<f64>,
+        to_bed: &Affine2<f64>,
+    ) -> MultiLineString<f64> {
+        MultiLineString::new(
+            lines
+                .0
+                .iter()
+                .map(|line| self.direction.orient(line, to_bed))
+                .collect(),
+        )
+    }
+
+    /// The line once for each pass, offset and reversed as configured.
+    /// Passes are never reversed if the pen only draws one way.
+    pub fn pass_lines(&self, line: &LineString<f64>) -> Vec<LineString<f64>> {
+        (0..self.passes.max(1))
+            .map(|pass| {
+                let nudge = pass as f64 * self.pass_offset / std::f64::consts::SQRT_2;
+                let mut points: Vec<Coord<f64>> = line
+                    .0
+                    .iter()
+                    .map(|point| Coord {
+                        x: point.x + nudge,
+                        y: point.y + nudge,
+                    })
+                    .collect();
+                if self.reverse_passes && self.reversible() && pass % 2 == 1 {
+                    points.reverse();
+                }
+                LineString::new(points)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::coord;
+
+    #[test]
+    fn test_pass_lines() {
+        let line = LineString::new(vec![coord! {x: 0., y: 0.}, coord! {x: 10., y: 0.}]);
+        assert_eq!(
+            PenPostSettings::default().pass_lines(&line),
+            vec![line.clone()]
+        );
+        let settings = PenPostSettings {
+            passes: 3,
+            pass_offset: std::f64::consts::SQRT_2,
+            reverse_passes: true,
+            ..Default::default()
+        };
+        let passes = settings.pass_lines(&line);
+        assert_eq!(passes.len(), 3);
+        assert_eq!(passes[0], line);
+        assert_eq!(
+            passes[1].0,
+            vec![coord! {x: 11., y: 1.}, coord! {x: 1., y: 1.}]
+        );
+        assert_eq!(passes[2].0[0], coord! {x: 2., y: 2.});
+    }
+
+    #[test]
+    fn test_draw_direction() {
+        use nalgebra::Matrix3;
+        let bed = Affine2::identity();
+        let line = LineString::new(vec![coord! {x: 10., y: 0.}, coord! {x: 0., y: 5.}]);
+        assert_eq!(DrawDirection::AsDrawn.orient(&line, &bed), line);
+        assert_eq!(DrawDirection::LeftToRight.orient(&line, &bed).0[0].x, 0.);
+        assert_eq!(DrawDirection::TopToBottom.orient(&line, &bed).0[0].y, 5.);
+        let ring = LineString::new(vec![
+            coord! {x: 10., y: 0.},
+            coord! {x: 0., y: 5.},
+            coord! {x: 10., y: 0.},
+        ]);
+        assert_eq!(DrawDirection::LeftToRight.orient(&ring, &bed), ring);
+
+        // A machine with X and Y swapped and Y inverted, so the bed's
+        // left to right is the machine's -Y and top to bottom its -X.
+        let swapped = Affine2::from_matrix_unchecked(Matrix3::new(
+            0., 1., 0., //
+            -1., 0., 0., //
+            0., 0., 1.,
+        ));
+        let to_bed = swapped.inverse();
+        let line = LineString::new(vec![coord! {x: 0., y: -10.}, coord! {x: 5., y: 0.}]);
+        assert_eq!(
+            DrawDirection::LeftToRight.orient(&line, &to_bed).0[0],
+            coord! {x: 5., y: 0.}
+        );
+        assert_eq!(
+            DrawDirection::TopToBottom.orient(&line, &to_bed).0[0],
+            coord! {x: 5., y: 0.}
+        );
+    }
+}
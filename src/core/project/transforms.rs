@@ -101,6 +101,8 @@ impl super::Project {
                     geometry: new_geo,
                     pen_uuid: pg.pen_uuid,
                     keepdown_strategy: pg.keepdown_strategy,
+                    pinned: pg.pinned,
+                    marks: pg.marks,
                 }
             })
             .collect()
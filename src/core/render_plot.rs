@@ -1,26 +1,46 @@
 // use super::post::LastMove;
 use egui::ColorImage;
-// TODO: I really need to write my own that has stateful G/M codes to remember the
-// previous move type, so that just coords can be used to reduce the outgoing bitrate
 use geo::{Coord, Rect};
+use nalgebra::{Affine2, Matrix3, Point2};
 use skia_safe::paint::Style;
 use skia_safe::{AlphaType, Bitmap, Color, ImageInfo, Paint, Path, PathEffect, surfaces};
 use std::sync::mpsc::{Receiver, Sender};
 
 use crate::core::commands::ApplicationStateChangeMsg;
+use crate::core::hpgl::parse_hpgl_moves;
+use crate::core::machine::MachineVariant;
+use crate::core::modal::parse_words;
+use crate::core::post::post_transform;
 use crate::core::project::Project;
 
-fn machine_coords_to_model_coords(xy: (f64, f64), origin: (f64, f64)) -> (f64, f64) {
-    let x = xy.0 + origin.0;
-    let y = origin.1 - xy.1;
-    (x, y)
+/// How big the pen crosshair is, in pixels.
+const CROSSHAIR_SIZE: f32 = 12.;
+
+fn machine_coords_to_model_coords(xy: (f64, f64), to_model: &Affine2<f64>) -> (f64, f64) {
+    let point = to_model.transform_point(&Point2::new(xy.0, xy.1));
+    (point.x, point.y)
+}
+
+/// Travel is red and drawing is blue, until the line's been sent. Then
+/// they're faded red and green, so it's clear how far into a pen the plot is.
+fn stroke_color(done: bool, drawing: bool) -> Color {
+    match (done, drawing) {
+        (false, false) => Color::RED,
+        (false, true) => Color::BLUE.with_a(128),
+        (true, false) => Color::RED.with_a(48),
+        (true, true) => Color::from_rgb(0, 160, 64),
+    }
 }
 
 pub(crate) fn render_plot_preview(
     project: &Project,
     // gc_item: &Vec<GCode>,
     extents: (f64, f64, f64, f64),
-    progress: (usize, usize),
+    // Lines sent, out of how many, and how many of those are still waiting
+    // on an ok.
+    progress: (usize, usize, usize),
+    // Where the plotter says the pen is (X, Y), in machine coordinates.
+    position: Option<(f64, f64)>,
     resolution: (usize, usize),
     _state_change_out: &Sender<ApplicationStateChangeMsg>,
     cancel: &Receiver<()>,
@@ -78,59 +98,129 @@ pub(crate) fn render_plot_preview(
         }
     }
 
-    let origin = if let Some(origin) = project.origin() {
-        origin
-    } else {
-        (0., 0.)
-    };
+    // The inverse of the post transform, falling back to just flipping Y.
+    let to_model = post_transform(project)
+        .ok()
+        .and_then(|tx| tx.try_inverse())
+        .unwrap_or(Affine2::from_matrix_unchecked(Matrix3::new(
+            1., 0., 0., 0., -1., 0., 0., 0., 1.,
+        )));
+    let is_hpgl = project.machine().unwrap_or_default().variant() == MachineVariant::HPGL;
     let mut px = 0.;
     let mut py = 0.;
-    // let mut last_move = LastMove::None;
+    let mut motion: Option<u32> = None;
+    // Lines still waiting on an ok may not have run yet, so they aren't
+    // drawn as done.
+    let acked = progress.0.saturating_sub(progress.2);
+    // Where the last line acked left the pen, for when there's no report.
+    let mut sent_position: Option<(f64, f64)> = None;
     for (idx, line) in project
         .program()
         .unwrap_or_else(|| Box::new(Vec::new()))
         .iter()
-        .take(progress.0)
         .enumerate()
     {
+        if let Ok(_msg) = cancel.try_recv() {
+            return Err(anyhow::anyhow!("Got a cancel on render."));
+        }
+        if idx == acked && acked > 0 {
+            sent_position = Some((px as f64, py as f64));
+        }
+        let done = idx < acked;
         // println!("GOT LINE: {}", line);
-        let gcodes = gcode::parse(line);
         let mut path = Path::new();
         paint.set_stroke_width(0.25);
-        let xy = machine_coords_to_model_coords((px as f64, py as f64), origin);
+        let xy = machine_coords_to_model_coords((px as f64, py as f64), &to_model);
         path.move_to((xy.0 as f32, xy.1 as f32));
         // println!("GCODE: {:?}", gcode);
-        if idx < progress.1 {
-            paint.set_path_effect(None);
-        } else {
-            paint.set_path_effect(PathEffect::dash(&[0.2, 0.5], 0.));
+        paint.set_path_effect(None);
+        if is_hpgl {
+            for (pen_down, x, y) in parse_hpgl_moves(line) {
+                (px, py) = (x as f32, y as f32);
+                let xy = machine_coords_to_model_coords((px as f64, py as f64), &to_model);
+                paint.set_color(stroke_color(done, pen_down));
+                path.line_to((xy.0 as f32, xy.1 as f32));
+                surface.canvas().draw_path(&path, &paint);
+            }
+            continue;
         }
-        for gcode_item in gcodes {
-            // println!("GCODE: {:?}", gcode_item);
-            match gcode_item.mnemonic() {
-                gcode::Mnemonic::General => {
-                    px = gcode_item.value_for('X').unwrap_or(px);
-                    py = gcode_item.value_for('Y').unwrap_or(py);
-                    let xy = machine_coords_to_model_coords((px as f64, py as f64), origin);
-                    let xy = (xy.0 as f32, xy.1 as f32);
-
-                    if gcode_item.major_number() == 0 {
-                        paint.set_color(Color::RED);
-                        path.line_to(xy);
-                        // last_move = LastMove::Move;
-                        // path.move_to(xy);
-                    } else if gcode_item.major_number() == 1 {
-                        paint.set_color(Color::BLUE.with_a(128));
-                        path.line_to(xy);
-                        // last_move = LastMove::Feed;
-                    }
-                }
-                gcode::Mnemonic::Miscellaneous => (), //last_move = LastMove::None,
-                gcode::Mnemonic::ProgramNumber => (), //last_move = LastMove::None,
-                gcode::Mnemonic::ToolChange => (),    //last_move = LastMove::None,
+        // Modal, so lines that are just coordinates keep the last motion mode.
+        let words = parse_words(line);
+        for (letter, value) in &words {
+            if *letter == 'G' && [0., 1., 2., 3.].contains(value) {
+                motion = Some(*value as u32);
             }
-            surface.canvas().draw_path(&path, &paint);
         }
+        let non_motion = words
+            .iter()
+            .any(|(letter, value)| *letter == 'G' && !(0.0..=3.0).contains(value));
+        let word = |axis: char| {
+            words
+                .iter()
+                .find(|(letter, value)| *letter == axis && !value.is_nan())
+                .map(|(_, value)| *value as f32)
+        };
+        if word('X').is_none() && word('Y').is_none() {
+            continue;
+        }
+        let (x0, y0) = (px, py);
+        px = word('X').unwrap_or(px);
+        py = word('Y').unwrap_or(py);
+        if non_motion {
+            // G92 and friends move us without drawing anything.
+            continue;
+        }
+        match motion {
+            Some(0) => paint.set_color(stroke_color(done, false)),
+            Some(_) => paint.set_color(stroke_color(done, true)),
+            None => continue,
+        };
+        if let Some(arc) = motion.filter(|m| *m == 2 || *m == 3) {
+            let (cx, cy) = (x0 + word('I').unwrap_or(0.), y0 + word('J').unwrap_or(0.));
+            let radius = ((x0 - cx).powi(2) + (y0 - cy).powi(2)).sqrt();
+            let a0 = (y0 - cy).atan2(x0 - cx);
+            let mut sweep = (py - cy).atan2(px - cx) - a0;
+            if arc == 2 && sweep >= 0. {
+                sweep -= 2. * std::f32::consts::PI;
+            } else if arc == 3 && sweep <= 0. {
+                sweep += 2. * std::f32::consts::PI;
+            }
+            let steps = ((sweep.abs() * radius).ceil() as usize).clamp(4, 256);
+            for step in 1..steps {
+                let angle = a0 + sweep * step as f32 / steps as f32;
+                let xy = machine_coords_to_model_coords(
+                    (
+                        (cx + radius * angle.cos()) as f64,
+                        (cy + radius * angle.sin()) as f64,
+                    ),
+                    &to_model,
+                );
+                path.line_to((xy.0 as f32, xy.1 as f32));
+            }
+        }
+        let xy = machine_coords_to_model_coords((px as f64, py as f64), &to_model);
+        path.line_to((xy.0 as f32, xy.1 as f32));
+        surface.canvas().draw_path(&path, &paint);
+    }
+    if acked > 0 && sent_position.is_none() {
+        // Everything's been acked.
+        sent_position = Some((px as f64, py as f64));
+    }
+
+    if let Some(xy) = position.or(sent_position) {
+        let (x, y) = machine_coords_to_model_coords(xy, &to_model);
+        let (x, y) = (x as f32, y as f32);
+        let size = CROSSHAIR_SIZE / sx;
+        let mut path = Path::new();
+        path.move_to((x - size, y));
+        path.line_to((x + size, y));
+        path.move_to((x, y - size));
+        path.line_to((x, y + size));
+        path.add_circle((x, y), size * 0.5, None);
+        paint.set_path_effect(None);
+        paint.set_stroke_width(0.);
+        paint.set_color(Color::MAGENTA);
+        surface.canvas().draw_path(&path, &paint);
     }
 
     let _context = surface.direct_context();
@@ -0,0 +1,378 @@
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+use nalgebra::Point2;
+
+use super::commands::ApplicationStateChangeMsg;
+use super::hpgl::parse_hpgl_moves;
+use super::machine::MachineVariant;
+use super::modal::parse_words;
+use super::post::{PostSummary, post_transform, slot_routine};
+use super::post_context::{JobBounds, job_context};
+use super::project::{PenDetail, Project};
+use super::sender::PlotterCommand;
+
+/// Where to pick a plot back up from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResumeFrom {
+    /// A program line. Rewinds to the start of the line being drawn there.
+    Line(usize),
+    /// The start of a geometry, by its index in the project's plot_geometry.
+    Geometry(usize),
+    /// The line drawn nearest this point, in project coordinates.
+    Point(f64, f64),
+}
+
+impl super::ApplicationCore {
+    /// Starts the posted program part way through.
+    pub fn handle_resume(&mut self, from: &ResumeFrom) {
+        let resumed = match (&self.program, &self.post_summary) {
+            (Some(program), Some(summary)) => {
+                resume_plot(&self.project, program, summary, from, self.progress.0)
+            }
+            _ => Err(anyhow!("Post the project before resuming a plot.")),
+        };
+        match resumed {
+            Ok((preamble, line)) => {
+                self.yolo_send_plotter_cmd(PlotterCommand::RunFrom(line as u32, Box::new(preamble)))
+            }
+            Err(err) => self.yolo_app_state_change(ApplicationStateChangeMsg::Error(format!(
+                "Can't resume plot: {}",
+                err
+            ))),
+        }
+    }
+}
+
+/// Works out where to resume, and the preamble to send first: the prelude,
+/// the tool change for the pen that was drawing, a pen up, then a rapid to
+/// the start of the line. Returns the preamble and the program line to
+/// carry on from. A changer's grab is replayed too, so the pen it was
+/// holding when the plot stopped (at stopped_at) is put back in its slot
+/// first.
+pub fn resume_plot(
+    project: &Project,
+    program: &[String],
+    summary: &PostSummary,
+    from: &ResumeFrom,
+    stopped_at: usize,
+) -> AnyResult<(Vec<String>, usize)> {
+    let machine = project.machine().ok_or(anyhow!("Invalid machine"))?;
+    let stroke = resume_stroke(project, program, summary, from)?;
+    let prelude_end = summary
+        .pen_starts
+        .first()
+        .map(|(line, _, _)| *line)
+        .unwrap_or(0);
+    let mut preamble: Vec<String> = program[..prelude_end.min(stroke)].to_vec();
+    let is_hpgl = machine.variant() == MachineVariant::HPGL;
+    let mut context = job_context(project, 0, &JobBounds::default());
+    if let Some(pen_z) = machine.pen_z() {
+        context.insert("zmm", &pen_z.travel);
+    }
+    if !is_hpgl
+        && let Some(slot) =
+            held_tool(project, summary, stopped_at).and_then(|tool| machine.tool_slot(tool))
+    {
+        let post_template = machine.post_template()?;
+        preamble.extend(
+            post_template
+                .render("penup", &context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        );
+        preamble.extend(slot_routine(
+            &post_template,
+            &context,
+            &slot,
+            &slot.release,
+            machine.feedrate(),
+        )?);
+    }
+    if let Some((start, end)) = summary
+        .tool_changes
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= stroke)
+    {
+        preamble.extend_from_slice(&program[*start..*end]);
+    }
+    if is_hpgl {
+        preamble.push("PU;".to_string());
+        // The velocity is only sent when it changes.
+        if let Some(velocity) = program[..stroke]
+            .iter()
+            .rev()
+            .find(|line| line.starts_with("VS"))
+        {
+            preamble.push(velocity.clone());
+        }
+    } else {
+        preamble.extend(
+            machine
+                .post_template()?
+                .render("penup", &context)?
+                .split("\n")
+                .map(|s| s.to_string()),
+        );
+    }
+    // Stroke starts stand on their own, so this is a complete rapid.
+    preamble.push(program[stroke].clone());
+    Ok((preamble, stroke + 1))
+}
+
+/// The tool a plot that stopped at this line left in the carriage: the one
+/// picked up by the last tool change before it, unless the job got as far
+/// as the epilog.
+fn held_tool(project: &Project, summary: &PostSummary, line: usize) -> Option<usize> {
+    if line >= summary.epilog_start {
+        return None;
+    }
+    let (change, _) = summary
+        .tool_changes
+        .iter()
+        .rev()
+        .find(|(_, end)| *end <= line)?;
+    let (_, uuid, _) = summary
+        .pen_starts
+        .iter()
+        .rev()
+        .find(|(start, _, _)| start <= change)?;
+    Some(
+        project
+            .pen_by_uuid(*uuid)
+            .unwrap_or(PenDetail::default())
+            .tool_id,
+    )
+}
+
+/// The stroke start (a pen up move to the start of a line) to resume at.
+fn resume_stroke(
+    project: &Project,
+    program: &[String],
+    summary: &PostSummary,
+    from: &ResumeFrom,
+) -> AnyResult<usize> {
+    let strokes = &summary.stroke_starts;
+    let stroke = match from {
+        ResumeFrom::Geometry(idx) => {
+            let start = summary
+                .geometry_starts
+                .iter()
+                .find(|(_, geometry)| geometry == idx)
+                .map(|(line, _)| *line)
+                .ok_or(anyhow!("Geometry {} isn't in the posted program.", idx))?;
+            strokes.iter().find(|line| **line >= start)
+        }
+        ResumeFrom::Line(line) => strokes.iter().rev().find(|start| *start <= line),
+        ResumeFrom::Point(x, y) => {
+            let line = nearest_line(project, program, summary, (*x, *y))?;
+            strokes.iter().rev().find(|start| **start <= line)
+        }
+    };
+    stroke
+        .or(strokes.first())
+        .copied()
+        .filter(|line| *line < summary.epilog_start && *line < program.len())
+        .ok_or(anyhow!("There's nothing left to draw."))
+}
+
+/// The drawing line whose move ends nearest the point (in project
+/// coordinates).
+fn nearest_line(
+    project: &Project,
+    program: &[String],
+    summary: &PostSummary,
+    point: (f64, f64),
+) -> AnyResult<usize> {
+    let target = post_transform(project)?.transform_point(&Point2::new(point.0, point.1));
+    let is_hpgl = project.machine().unwrap_or_default().variant() == MachineVariant::HPGL;
+    let first = summary.stroke_starts.first().copied().unwrap_or(0);
+    let mut position: Option<(f64, f64)> = None;
+    let mut nearest: Option<(f64, usize)> = None;
+    for (idx, line) in program.iter().enumerate().take(summary.epilog_start) {
+        position = if is_hpgl {
+            parse_hpgl_moves(line)
+                .last()
+                .map(|(_, x, y)| (*x, *y))
+                .or(position)
+        } else {
+            let words = parse_words(line);
+            let word = |axis: char| {
+                words
+                    .iter()
+                    .find(|(letter, value)| *letter == axis && !value.is_nan())
+                    .map(|(_, value)| *value)
+            };
+            match (word('X'), word('Y')) {
+                (None, None) => position,
+                (x, y) => {
+                    let (px, py) = position.unwrap_or((0., 0.));
+                    Some((x.unwrap_or(px), y.unwrap_or(py)))
+                }
+            }
+        };
+        if idx < first {
+            continue;
+        }
+        if let Some((x, y)) = position {
+            let distance = (x - target.x).hypot(y - target.y);
+            if nearest.is_none_or(|(best, _)| distance < best) {
+                nearest = Some((distance, idx));
+            }
+        }
+    }
+    nearest
+        .map(|(_, idx)| idx)
+        .ok_or(anyhow!("There's nothing drawn to resume from."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::PostOptions;
+    use crate::core::machine::MachineConfig;
+    use crate::core::post::post;
+    use crate::core::project::{BAPGeometry, GeometryKind, KeepdownStrategy};
+    use geo::{Geometry, LineString, coord};
+
+    #[test]
+    fn test_resume_plot() {
+        let mut project = Project::new();
+        let mut machine = MachineConfig::default();
+        machine.set_modal(true);
+        project.set_machine(Some(machine));
+        project.set_origin(&Some((0., 100.)));
+        let pen = PenDetail::default();
+        project.pens.push(pen.clone());
+        for y in [10.5, 20.5] {
+            project.plot_geometry.push(BAPGeometry {
+                pen_uuid: pen.identity,
+                name: format!("line at {}", y),
+                geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(vec![
+                    coord! {x: 0., y: y},
+                    coord! {x: 10., y: y},
+                ]))),
+                keepdown_strategy: KeepdownStrategy::None,
+                pinned: false,
+                marks: false,
+            });
+        }
+        let (program, summary) = post(&project, &PostOptions::default()).expect("Failed to post");
+        let (preamble, line) =
+            resume_plot(&project, &program, &summary, &ResumeFrom::Geometry(1), 0)
+                .expect("Failed to resume");
+        let prelude_end = summary.pen_starts[0].0;
+        assert_eq!(preamble[..prelude_end], program[..prelude_end]);
+        let (start, end) = summary.tool_changes[0];
+        assert_eq!(
+            preamble[prelude_end..prelude_end + end - start],
+            program[start..end]
+        );
+        // The rapid to the second line has to say where it's going in full.
+        let rapid = preamble.last().unwrap();
+        assert!(
+            rapid.starts_with("G0 X") && rapid.contains("Y79.5"),
+            "{}",
+            rapid
+        );
+        assert_eq!(program[line - 1], *rapid);
+        for from in [
+            ResumeFrom::Line(summary.epilog_start - 1),
+            ResumeFrom::Point(5., 20.5),
+        ] {
+            assert_eq!(
+                resume_plot(&project, &program, &summary, &from, 0).expect("Failed to resume"),
+                (preamble.clone(), line)
+            );
+        }
+    }
+
+    #[test]
+    fn test_resume_changer() {
+        use crate::core::machine::ToolSlot;
+        use uuid::Uuid;
+        let mut project = Project::new();
+        project.set_machine(Some(MachineConfig::default()));
+        project.set_origin(&Some((0., 100.)));
+        let first = PenDetail::default();
+        let second = PenDetail {
+            identity: Uuid::new_v4(),
+            tool_id: first.tool_id + 1,
+            ..PenDetail::default()
+        };
+        for (pen, y) in [(&first, 10.), (&second, 20.)] {
+            project.pens.push(pen.clone());
+            project.plot_geometry.push(BAPGeometry {
+                pen_uuid: pen.identity,
+                name: format!("line at {}", y),
+                geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(vec![
+                    coord! {x: 0., y: y},
+                    coord! {x: 10., y: y},
+                ]))),
+                keepdown_strategy: KeepdownStrategy::None,
+                pinned: false,
+                marks: false,
+            });
+        }
+        let mut machine = project.machine().unwrap();
+        machine.set_tool_slots(
+            [&first, &second]
+                .iter()
+                .enumerate()
+                .map(|(slot, pen)| ToolSlot {
+                    tool_id: pen.tool_id,
+                    position: (200., 20. * slot as f64),
+                    approach: (-10., 0.),
+                    grab: "M5 ; GRAB T{{tool_id}}".to_string(),
+                    release: "M3 ; RELEASE T{{tool_id}}".to_string(),
+                })
+                .collect(),
+        );
+        project.set_machine(Some(machine));
+        let (program, summary) = post(&project, &PostOptions::default()).expect("Failed to post");
+        let changes = |preamble: &Vec<String>| -> Vec<String> {
+            preamble
+                .iter()
+                .filter(|line| line.contains("GRAB") || line.contains("RELEASE"))
+                .cloned()
+                .collect()
+        };
+        // Nothing's been drawn, so the carriage is empty.
+        let (preamble, _) = resume_plot(&project, &program, &summary, &ResumeFrom::Geometry(0), 0)
+            .expect("Failed to resume");
+        assert_eq!(
+            changes(&preamble),
+            vec![format!("M5 ; GRAB T{}", first.tool_id)]
+        );
+        // Stopped drawing with the second pen, and going back to the first.
+        let stopped_at = summary.geometry_starts[1].0 + 1;
+        let (preamble, _) = resume_plot(
+            &project,
+            &program,
+            &summary,
+            &ResumeFrom::Geometry(0),
+            stopped_at,
+        )
+        .expect("Failed to resume");
+        assert_eq!(
+            changes(&preamble),
+            vec![
+                format!("M3 ; RELEASE T{}", second.tool_id),
+                format!("M5 ; GRAB T{}", first.tool_id),
+            ]
+        );
+        // All done, and every pen's been put away.
+        let (preamble, _) = resume_plot(
+            &project,
+            &program,
+            &summary,
+            &ResumeFrom::Geometry(1),
+            program.len(),
+        )
+        .expect("Failed to resume");
+        assert_eq!(
+            changes(&preamble),
+            vec![format!("M5 ; GRAB T{}", second.tool_id)]
+        );
+    }
+}
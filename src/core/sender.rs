@@ -1,18 +1,32 @@
 use anyhow::Result as AnyResult;
 use anyhow::anyhow;
 use mpsc::{Receiver, Sender};
-use serialport;
+use serialport::{self, FlowControl, SerialPort};
+use std::collections::VecDeque;
 use std::fmt;
 use std::io::Write;
 use std::io::{BufRead, BufReader, BufWriter};
 use std::num::ParseIntError;
 use std::ops::DerefMut;
 use std::sync::mpsc::{self, TryRecvError};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use super::machine::{MachineVariant, Streaming};
+use super::sim::SimTransport;
+use super::status::MachineStatus;
+use super::tcp::TcpTransport;
 
 const DEFAULT_TIMEOUT: u64 = 30000;
 const DEFAULT_BAUDRATE: u64 = 115200 * 2;
 const MAX_OKS_BACKLOG: usize = 8;
+/// GRBL's realtime commands. They're single bytes that get acted on as soon
+/// as they arrive, rather than queued up behind the lines already sent.
+const FEED_HOLD: u8 = b'!';
+const CYCLE_START: u8 = b'~';
+const SOFT_RESET: u8 = 0x18;
+const STATUS_QUERY: u8 = b'?';
+/// How often GRBL gets asked for a status report.
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -41,12 +55,21 @@ impl From<serialport::Error> for PlotterConnectionError {
     }
 }
 
+impl From<std::io::Error> for PlotterConnectionError {
+    fn from(error: std::io::Error) -> Self {
+        PlotterConnectionError::DeviceError(error.to_string())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PlotterCommand {
-    Connect(String), // A URL to connect to (/dev/ttyACM0, telnet://foo:1234, etc)
+    Connect(String), // A URL to connect to (serial:///dev/ttyACM0, telnet://foo:1234, etc)
     Disconnect,
     Program(Box<Vec<String>>),
+    SetVariant(MachineVariant), // Which dialect we're streaming (GRBL waits for oks, HPGL doesn't).
+    SetStreaming(Streaming, usize), // How to pace lines, and the controller's RX buffer size.
     Run,
+    RunFrom(u32, Box<Vec<String>>), // Start at a line, after sending the preamble.
     Stop,
     Reset,
     Command(String),
@@ -69,12 +92,13 @@ pub enum PlotterState {
     Dead,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PlotterResponse {
     Ok(PlotterCommand, String),
     Loaded(String),
     Err(PlotterCommand, String),
-    State(PlotterState), // progress and msg
+    State(PlotterState),   // progress and msg
+    Status(MachineStatus), // What GRBL says it's doing, and where.
 }
 
 pub struct PlotterConnection {
@@ -82,12 +106,24 @@ pub struct PlotterConnection {
     // recv: Receiver<PlotterCommand>,
     transport: Option<TransportTypes>,
     program: Option<Box<Vec<String>>>,
+    // Sent ahead of the program when resuming part way through.
+    preamble: VecDeque<String>,
     // position: Option<usize>,
     state: PlotterState,
     recv: Receiver<PlotterCommand>,
     send: Sender<PlotterResponse>,
     ticks: usize,
     oks: usize,
+    // The size of each line still waiting on an ok, oldest first.
+    in_flight: VecDeque<usize>,
+    variant: MachineVariant,
+    streaming: Streaming,
+    rx_buffer: usize,
+    // Whether the controller greeted us as GRBL, so takes realtime commands.
+    realtime: bool,
+    last_status: Instant,
+    // The last work offset GRBL reported, which it only does now and then.
+    wco: Option<(f64, f64, f64)>,
 }
 
 impl fmt::Debug for PlotterConnection {
@@ -110,18 +146,130 @@ impl PlotterConnection {
             let mut me = PlotterConnection {
                 transport: None,
                 program: None,
+                preamble: VecDeque::new(),
                 // position: None,
                 state: PlotterState::default(),
                 send: respsend,
                 recv: cmdrecv,
                 ticks: 0,
                 oks: 0,
+                in_flight: VecDeque::new(),
+                variant: MachineVariant::default(),
+                streaming: Streaming::default(),
+                rx_buffer: 128,
+                realtime: false,
+                last_status: Instant::now(),
+                wco: None,
             };
             me.run();
         });
         Ok((cmdsend, resprecv))
     }
 
+    /// HPGL plotters don't acknowledge anything, they just rely on
+    /// flow control, so there's nothing to wait for.
+    fn expects_ok(&self) -> bool {
+        self.variant != MachineVariant::HPGL
+    }
+
+    /// Character counting only waits on an ok once the next line won't fit
+    /// in the controller's buffer. Ok counting waits after every line.
+    fn should_wait_ok(&self) -> bool {
+        let PlotterState::Running(line, _, _) = self.state else {
+            return true;
+        };
+        let next = self.preamble.front().or(self
+            .program
+            .as_ref()
+            .and_then(|program| program.get(line as usize)));
+        self.streaming == Streaming::OkCounting
+            || next.is_none_or(|next| !fits(&self.in_flight, self.rx_buffer, next))
+    }
+
+    /// Whether there's a response waiting to be read. A transport that can't
+    /// tell is read anyway, so whatever's wrong with it comes out.
+    fn has_input(&mut self) -> bool {
+        self.transport
+            .as_mut()
+            .is_some_and(|transport| transport.has_input().unwrap_or(true))
+    }
+
+    /// Sends a realtime command, if the controller understands them. Marlin
+    /// would take the byte as the start of the next line, so it's left out.
+    fn send_realtime(&mut self, byte: u8) {
+        if !self.realtime {
+            return;
+        }
+        if let Some(transport) = self.transport.as_mut()
+            && let Err(err) = transport.write_realtime(byte)
+        {
+            eprintln!("Failed to send realtime command {:#04x}: {:?}", byte, err);
+        }
+    }
+
+    /// Resets GRBL, dropping whatever it has buffered, and waits for it to
+    /// come back. Nothing that was in flight is going to get an ok now.
+    fn soft_reset(&mut self) {
+        self.send_realtime(SOFT_RESET);
+        self.oks = 0;
+        self.in_flight.clear();
+        let Some(transport) = self.transport.as_mut() else {
+            return;
+        };
+        let mut line = String::with_capacity(80);
+        loop {
+            line.clear();
+            match transport.read_line(&mut line) {
+                Ok(count) if count > 0 => {
+                    if line.starts_with("Grbl") {
+                        return;
+                    }
+                }
+                _ => {
+                    eprintln!("No banner from the plotter after a reset.");
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Asks GRBL for a status report while the sender is idle, and waits on
+    /// the answer. Any outstanding oks that turn up first are counted off.
+    /// A controller that doesn't answer isn't asked again.
+    fn poll_status(&mut self) {
+        if !self.realtime || self.last_status.elapsed() < STATUS_INTERVAL {
+            return;
+        }
+        self.last_status = Instant::now();
+        self.send_realtime(STATUS_QUERY);
+        let Some(transport) = self.transport.as_mut() else {
+            return;
+        };
+        let mut line = String::with_capacity(80);
+        loop {
+            line.clear();
+            match transport.read_line(&mut line) {
+                Ok(count) if count > 0 => {
+                    if let Some(mut status) = MachineStatus::parse(&line) {
+                        status.offset_by(&mut self.wco);
+                        self.send
+                            .send(PlotterResponse::Status(status))
+                            .expect("Cannot send status to parent thread");
+                        return;
+                    } else if line.starts_with("ok") && self.oks > 0 {
+                        self.oks -= 1;
+                        self.in_flight.pop_front();
+                    }
+                }
+                _ => {
+                    eprintln!("No status report from the plotter. Not asking again.");
+                    self.realtime = false;
+                    return;
+                }
+            }
+        }
+    }
+
     fn wait_ok(&mut self) -> AnyResult<()> {
         let tx: &mut TransportTypes = self.transport.as_mut().unwrap(); // I literally just set it.
         let mut banner = String::with_capacity(80);
@@ -135,14 +283,25 @@ impl PlotterConnection {
                     } else {
                         if banner.starts_with("ok") {
                             return Ok(());
-                        } else if banner.starts_with("!!") {
-                            return Err(anyhow!("Plotter error: {}", &banner));
+                        } else if banner.starts_with("Grbl") {
+                            self.realtime = true;
+                        } else if let Some(mut status) = MachineStatus::parse(&banner) {
+                            // Answering an earlier status query.
+                            status.offset_by(&mut self.wco);
+                            self.send
+                                .send(PlotterResponse::Status(status))
+                                .expect("Cannot send status to parent thread");
+                        } else if banner.starts_with("!!")
+                            || banner.to_lowercase().starts_with("error:")
+                            || banner.starts_with("ALARM:")
+                        {
+                            return Err(anyhow!("Plotter error: {}", banner.trim()));
                         }
                     };
                 }
-                Err(_) => {
+                Err(err) => {
                     // Ruh Roh
-                    return Err(anyhow!("EOF"));
+                    return Err(anyhow!("EOF: {}", err));
                 }
             }
         }
@@ -164,13 +323,17 @@ impl PlotterConnection {
                 match TransportTypes::from_uri(conn_detail.as_str()) {
                     Ok(transport) => {
                         self.transport = Some(transport);
+                        self.realtime = false;
+                        self.wco = None;
+                        self.oks = 0;
+                        self.in_flight.clear();
                         self.send
                             .send(PlotterResponse::Ok(
                                 message.clone(),
                                 "Connected".to_string(),
                             ))
                             .expect("Failed to send response?");
-                        if let Ok(_) = self.wait_ok() {
+                        if !self.expects_ok() || self.wait_ok().is_ok() {
                             self.set_state(PlotterState::Ready)
                                 .expect("Failed to set state?");
                         } else {
@@ -216,6 +379,7 @@ impl PlotterConnection {
             },
             PlotterCommand::Program(program) => {
                 self.program = Some(program.clone());
+                self.preamble.clear();
                 self.send
                     .send(PlotterResponse::Loaded(format!(
                         "Loaded {} lines.",
@@ -223,10 +387,31 @@ impl PlotterConnection {
                     )))
                     .expect("Cannot send OK response to parent thread");
             }
+            PlotterCommand::SetVariant(variant) => {
+                self.variant = variant.clone();
+                self.send
+                    .send(PlotterResponse::Ok(
+                        message.clone(),
+                        format!("Streaming as {}.", variant),
+                    ))
+                    .expect("Cannot send OK response to parent thread");
+            }
+            PlotterCommand::SetStreaming(streaming, rx_buffer) => {
+                self.streaming = streaming.clone();
+                self.rx_buffer = *rx_buffer;
+                self.send
+                    .send(PlotterResponse::Ok(
+                        message.clone(),
+                        format!("Streaming with {} ({} byte buffer).", streaming, rx_buffer),
+                    ))
+                    .expect("Cannot send OK response to parent thread");
+            }
             PlotterCommand::Run => match &self.state {
                 PlotterState::Running(_line, _lines, _oks) => {}
                 PlotterState::Paused(line, lines, _oks) => {
-                    self.set_state(PlotterState::Running(*line, *lines, self.oks as u32))
+                    let (line, lines) = (*line, *lines);
+                    self.send_realtime(CYCLE_START);
+                    self.set_state(PlotterState::Running(line, lines, self.oks as u32))
                         .expect("Couldn't set resume running state after pause.");
                     self.send
                         .send(PlotterResponse::Ok(
@@ -239,6 +424,7 @@ impl PlotterConnection {
                     match &self.program {
                         Some(program) => {
                             self.oks = 0;
+                            self.in_flight.clear();
                             self.set_state(PlotterState::Running(
                                 0,
                                 program.len() as u32,
@@ -257,9 +443,42 @@ impl PlotterConnection {
                 }
                 _ => {}
             },
+            PlotterCommand::RunFrom(line, preamble) => match (&self.state, &self.program) {
+                (PlotterState::Ready | PlotterState::Paused(_, _, _), Some(program)) => {
+                    let lines = program.len() as u32;
+                    self.preamble = preamble.iter().cloned().collect();
+                    if matches!(self.state, PlotterState::Paused(_, _, _)) && self.realtime {
+                        // Carrying on from the hold would draw out everything
+                        // still buffered first, so throw it away instead.
+                        self.soft_reset();
+                    } else {
+                        self.send_realtime(CYCLE_START);
+                    }
+                    self.set_state(PlotterState::Running(*line, lines, self.oks as u32))
+                        .expect("Couldn't set state to running!");
+                    self.send
+                        .send(PlotterResponse::Ok(
+                            message.clone(),
+                            format!("Program resumed from line {}.", line),
+                        ))
+                        .expect("Cannot send OK response to parent thread");
+                }
+                _ => {
+                    self.send
+                        .send(PlotterResponse::Err(
+                            message.clone(),
+                            format!("Invalid state {:?} for resume command.", &self.state),
+                        ))
+                        .expect("Cannot send error response to parent thread");
+                }
+            },
             PlotterCommand::Stop => match &self.state {
                 PlotterState::Running(line, lines, oks) => {
-                    self.set_state(PlotterState::Paused(*line, *lines, *oks))
+                    // GRBL can stop mid move, rather than drawing out
+                    // everything it's already buffered.
+                    let paused = PlotterState::Paused(*line, *lines, *oks);
+                    self.send_realtime(FEED_HOLD);
+                    self.set_state(paused)
                         .expect("Cannot set paused state in sender thread.");
                     self.send
                         .send(PlotterResponse::Ok(
@@ -280,7 +499,9 @@ impl PlotterConnection {
             },
             PlotterCommand::Reset => {
                 eprintln!("Got serial connection reset.");
+                self.send_realtime(SOFT_RESET);
                 self.transport = None;
+                self.preamble.clear();
                 self.set_state(PlotterState::Disconnected)
                     .expect("Cannot send disconnected state to parent thread");
                 self.send
@@ -298,7 +519,7 @@ impl PlotterConnection {
                         // println!("Sending command: '{}'", &cmd);
                         transport.write_line(&cmd).expect("Failed to send line.");
                         transport.flush().expect("Failed to flush line.");
-                        if let Ok(_) = self.wait_ok() {
+                        if !self.expects_ok() || self.wait_ok().is_ok() {
                             self.set_state(PlotterState::Ready)
                                 .expect("Failed to set state?");
                         } else {
@@ -361,40 +582,72 @@ impl PlotterConnection {
                 PlotterState::Connecting(_) => {
                     std::thread::sleep(std::time::Duration::from_millis(100))
                 }
-                PlotterState::Ready => std::thread::sleep(std::time::Duration::from_millis(100)),
-                PlotterState::Paused(_, _, _) => {
-                    std::thread::sleep(std::time::Duration::from_millis(100))
+                PlotterState::Ready | PlotterState::Paused(_, _, _) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    self.poll_status();
                 }
                 PlotterState::Running(current_line, total_lines, _oks) => {
-                    if self.oks < MAX_OKS_BACKLOG {
+                    // Character counting is held back by the bytes in flight
+                    // instead, however many lines that comes to.
+                    if self.streaming == Streaming::CharacterCounting || self.oks < MAX_OKS_BACKLOG
+                    {
                         // Used to be 5. Reducing for less choking?
                         match &mut self.transport {
                             Some(transport) => {
                                 // println!("Transport: {:?}", &transport);
                                 if let Some(program) = &self.program {
-                                    if let Some(line) = program.get(current_line as usize).clone() {
+                                    // A resume sends its preamble before carrying on
+                                    // with the program, without moving the line.
+                                    let from_preamble = !self.preamble.is_empty();
+                                    let next_line = match from_preamble {
+                                        true => self.preamble.front().cloned(),
+                                        false => program.get(current_line as usize).cloned(),
+                                    };
+                                    let after_line = match from_preamble {
+                                        true => current_line,
+                                        false => current_line + 1,
+                                    };
+                                    // Character counting holds lines back until
+                                    // they fit in the controller's buffer.
+                                    if let Some(line) = next_line
+                                        && (self.streaming == Streaming::OkCounting
+                                            || fits(&self.in_flight, self.rx_buffer, &line))
+                                    {
                                         if line.to_uppercase().trim().starts_with("$M06") {
                                             eprintln!("Running tool change. '{}'", line);
+                                            if from_preamble {
+                                                self.preamble.pop_front();
+                                            }
                                             self.set_state(PlotterState::Paused(
-                                                current_line + 1,
+                                                after_line,
                                                 total_lines,
                                                 self.oks as u32,
                                             ))
                                             .expect("Failed to M06 ToolChange pause the machine.");
                                             continue;
                                         }
-                                        match transport.write_line(line) {
+                                        // A network plotter only finds out it's been
+                                        // dropped when it flushes.
+                                        match transport
+                                            .write_line(&line)
+                                            .and_then(|_| transport.flush())
+                                        {
                                             Ok(_) => {
-                                                transport
-                                                    .flush()
-                                                    .expect("Cannot flush commands to plotter.");
+                                                if from_preamble {
+                                                    self.preamble.pop_front();
+                                                }
+                                                if self.expects_ok() {
+                                                    self.oks += 1;
+                                                    self.in_flight.push_back(line.len() + 1);
+                                                }
+                                                // Counting this line, so the preview knows
+                                                // how much is still waiting.
                                                 self.set_state(PlotterState::Running(
-                                                    current_line + 1u32,
+                                                    after_line,
                                                     total_lines.clone(),
                                                     self.oks as u32,
                                                 ))
                                                 .expect("Failed to update state");
-                                                self.oks += 1;
                                             }
                                             Err(err) => {
                                                 eprintln!(
@@ -409,6 +662,8 @@ impl PlotterConnection {
                                                 .expect("Cloudn't set error/fail state");
                                             }
                                         }
+                                    } else if from_preamble || current_line < total_lines {
+                                        // Waiting for room in the buffer.
                                     } else {
                                         eprintln!("No lines left. Done plot.");
                                     }
@@ -424,17 +679,24 @@ impl PlotterConnection {
                             }
                         }
 
-                        // Next, wait for outstanding OKs
-                        if self.oks > 0 {
+                        // The report comes back among the oks.
+                        if self.realtime && self.last_status.elapsed() >= STATUS_INTERVAL {
+                            self.last_status = Instant::now();
+                            self.send_realtime(STATUS_QUERY);
+                        }
+                        // Next, wait for outstanding OKs, and count off any
+                        // that are already back.
+                        if self.oks > 0 && (self.should_wait_ok() || self.has_input()) {
                             // println!("Still waiting for {} OKs.", self.oks);
                             match &mut self.transport {
                                 Some(_transport) => match self.wait_ok() {
                                     Ok(_) => {
                                         self.oks -= 1;
+                                        self.in_flight.pop_front();
                                     }
 
-                                    Err(_err) => {
-                                        self.set_state(PlotterState::Disconnected)
+                                    Err(err) => {
+                                        self.set_state(PlotterState::Failed(err.to_string()))
                                             .expect("Cannot set state after failure.");
                                     }
                                 },
@@ -443,7 +705,8 @@ impl PlotterConnection {
                                     .expect("No transport!!! Disconnecting."),
                             }
                         }
-                        if self.oks == 0 && current_line == total_lines {
+                        if self.oks == 0 && current_line == total_lines && self.preamble.is_empty()
+                        {
                             self.set_state(PlotterState::Ready)
                                 .expect("Can't go back to ready state.");
                         }
@@ -471,23 +734,71 @@ impl PlotterConnection {
     }
 }
 
+/// Whether a line fits in the controller's receive buffer alongside the
+/// ones still waiting on an ok, counting its newline. A line longer than
+/// the whole buffer goes once the buffer is empty.
+fn fits(in_flight: &VecDeque<usize>, rx_buffer: usize, line: &str) -> bool {
+    in_flight.is_empty() || in_flight.iter().sum::<usize>() + line.len() < rx_buffer
+}
+
 pub trait PlotterTransport {
     fn write_line(&mut self, buf: &str) -> std::io::Result<()>;
+    /// Sends a single byte straight away, with no newline.
+    fn write_realtime(&mut self, byte: u8) -> std::io::Result<()>;
     fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize>;
     fn flush(&mut self) -> std::io::Result<()>;
+    /// Whether something's come in, so read_line won't sit waiting for it.
+    fn has_input(&mut self) -> std::io::Result<bool>;
 }
 
 pub enum TransportTypes {
-    SerialReadWrite(Box<dyn BufRead>, Box<dyn Write>),
+    SerialReadWrite(Box<BufReader<Box<dyn SerialPort>>>, Box<dyn Write>),
+    Tcp(TcpTransport),
+    Sim(Box<SimTransport>),
 }
 
 impl TransportTypes {
     /// Given a URI in the form of serial:///dev/ttySomethingOrOther@115200,
     /// open up a serial connection on the /dev/ttySomethingOrOther at 115200 bps.
+    /// Adding ?flow=hardware or ?flow=software turns on RTS/CTS or XON/XOFF
+    /// flow control, which HPGL plotters need since they never send "ok".
+    ///
+    /// Network plotters are tcp://host:port or telnet://host:port (port 23
+    /// if left off), with an optional ?timeout= in milliseconds. A sim://
+    /// URI gets a simulated controller instead (see SimTransport).
     pub fn from_uri(uri: &str) -> Result<TransportTypes, PlotterConnectionError> {
         let url = url::Url::parse(uri)?;
         let default_baudrate = format!("{}", DEFAULT_BAUDRATE).to_string();
-        if url.scheme() == "serial" {
+        if url.scheme() == "tcp" || url.scheme() == "telnet" {
+            let telnet = url.scheme() == "telnet";
+            let host = url
+                .host_str()
+                .ok_or(PlotterConnectionError::ParseError(format!(
+                    "No host in {}",
+                    uri
+                )))?;
+            let port = match (url.port(), telnet) {
+                (Some(port), _) => port,
+                (None, true) => 23,
+                (None, false) => {
+                    return Err(PlotterConnectionError::ParseError(format!(
+                        "No port in {}",
+                        uri
+                    )));
+                }
+            };
+            let timeout = match url.query_pairs().find(|(key, _)| key == "timeout") {
+                Some((_, value)) => value.parse::<u64>()?,
+                None => DEFAULT_TIMEOUT,
+            };
+            Ok(TransportTypes::Tcp(TcpTransport::connect(
+                &format!("{}:{}", host, port),
+                Duration::from_millis(timeout),
+                telnet,
+            )?))
+        } else if url.scheme() == "sim" {
+            Ok(TransportTypes::Sim(Box::new(SimTransport::from_url(&url)?)))
+        } else if url.scheme() == "serial" {
             let mut parts: Vec<&str> = url.path().split("@").collect();
             if parts.len() == 1 {
                 parts.push(default_baudrate.as_str()); // default to 115200 baud
@@ -495,8 +806,19 @@ impl TransportTypes {
             if parts.len() == 2 {
                 let path = parts[0].to_string();
                 let bps = parts[1].to_string().parse::<u32>()?;
+                let flow_control = match url
+                    .query_pairs()
+                    .find(|(key, _)| key == "flow")
+                    .map(|(_, value)| value.to_lowercase())
+                    .as_deref()
+                {
+                    Some("hardware") => FlowControl::Hardware,
+                    Some("software") => FlowControl::Software,
+                    _ => FlowControl::None,
+                };
                 let sp = serialport::new(path, bps)
                     .timeout(Duration::from_millis(DEFAULT_TIMEOUT))
+                    .flow_control(flow_control)
                     .open()?;
                 let reader = BufReader::new(sp.try_clone()?);
                 let writer = BufWriter::new(sp);
@@ -520,6 +842,8 @@ impl fmt::Debug for TransportTypes {
             "PlotterTransport: {}",
             match self {
                 TransportTypes::SerialReadWrite(_, _) => "Serial",
+                TransportTypes::Tcp(_) => "TCP",
+                TransportTypes::Sim(_) => "Simulated",
             }
         )
     }
@@ -532,18 +856,45 @@ impl PlotterTransport for TransportTypes {
             TransportTypes::SerialReadWrite(_, bwrite) => bwrite
                 .deref_mut()
                 .write_all((buf.to_owned() + "\n").as_bytes()),
+            TransportTypes::Tcp(tcp) => tcp.write_line(buf),
+            TransportTypes::Sim(sim) => sim.write_line(buf),
+        }
+    }
+
+    fn write_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        match self {
+            TransportTypes::SerialReadWrite(_, bwrite) => {
+                bwrite.deref_mut().write_all(&[byte])?;
+                bwrite.deref_mut().flush()
+            }
+            TransportTypes::Tcp(tcp) => tcp.write_realtime(byte),
+            TransportTypes::Sim(sim) => sim.write_realtime(byte),
         }
     }
 
     fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize> {
         match self {
             TransportTypes::SerialReadWrite(bread, _) => bread.deref_mut().read_line(buf),
+            TransportTypes::Tcp(tcp) => tcp.read_line(buf),
+            TransportTypes::Sim(sim) => sim.read_line(buf),
         }
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             TransportTypes::SerialReadWrite(_, bwrite) => bwrite.deref_mut().flush(),
+            TransportTypes::Tcp(tcp) => tcp.flush(),
+            TransportTypes::Sim(sim) => sim.flush(),
+        }
+    }
+
+    fn has_input(&mut self) -> std::io::Result<bool> {
+        match self {
+            TransportTypes::SerialReadWrite(bread, _) => {
+                Ok(!bread.buffer().is_empty() || bread.get_ref().bytes_to_read()? > 0)
+            }
+            TransportTypes::Tcp(tcp) => tcp.has_input(),
+            TransportTypes::Sim(sim) => sim.has_input(),
         }
     }
 }
@@ -551,6 +902,7 @@ impl PlotterTransport for TransportTypes {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::core::status::MachineState;
     // use std::time::Duration;
 
     /*
@@ -568,17 +920,36 @@ mod test {
     }
     */
 
+    /// Collects responses up to the first one that matches.
+    fn wait_for(
+        resprecv: &Receiver<PlotterResponse>,
+        until: impl Fn(&PlotterResponse) -> bool,
+    ) -> Vec<PlotterResponse> {
+        let mut seen = Vec::new();
+        loop {
+            let response = resprecv
+                .recv_timeout(Duration::from_secs(10))
+                .expect("Timed out waiting on the plotter");
+            let done = until(&response);
+            seen.push(response);
+            if done {
+                return seen;
+            }
+        }
+    }
+
     #[test]
     fn test_happy_path() {
         let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+        let ready = |resp: &PlotterResponse| *resp == PlotterResponse::State(PlotterState::Ready);
 
         // println!("Spawned");
         cmdsend
             .send(PlotterCommand::Connect(
-                "serial:///dev/ttyACM0@1000000".to_string(),
+                "sim://marlin?latency=1&buffer=4".to_string(),
             ))
             .unwrap();
-        std::thread::sleep(Duration::from_millis(1000));
+        wait_for(&resprecv, ready);
         let program = Box::new(vec![
             "G28 X Y".to_string(),
             "M280 S5".to_string(),
@@ -598,33 +969,242 @@ mod test {
         cmdsend.send(PlotterCommand::Program(program)).unwrap();
         cmdsend.send(PlotterCommand::Run).unwrap();
 
-        loop {
-            if let Ok(resp) = resprecv.recv() {
-                // println!("Response:");
-                // println!("\t{:?}", &resp);
-                match resp {
-                    PlotterResponse::Ok(_, _) => (),
-                    PlotterResponse::Loaded(_) => println!("Program loaded."),
-                    PlotterResponse::Err(_, _) => break,
-                    PlotterResponse::State(_) => (),
-                }
-            }
+        let seen = wait_for(&resprecv, ready);
+        assert!(seen.iter().any(|resp| matches!(
+            resp,
+            PlotterResponse::State(PlotterState::Running(14, 14, _))
+        )));
+        cmdsend.send(PlotterCommand::Shutdown).unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Dead)
+        });
+    }
+
+    #[test]
+    fn test_plotter_error() {
+        let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+        cmdsend
+            .send(PlotterCommand::Connect("sim://grbl?alarm=3".to_string()))
+            .unwrap();
+        cmdsend
+            .send(PlotterCommand::Program(Box::new(
+                (0..5).map(|idx| format!("G1 X{}", idx)).collect(),
+            )))
+            .unwrap();
+        cmdsend.send(PlotterCommand::Run).unwrap();
+        let seen = wait_for(&resprecv, |resp| {
+            matches!(resp, PlotterResponse::State(PlotterState::Failed(_)))
+        });
+        assert_eq!(
+            seen.last(),
+            Some(&PlotterResponse::State(PlotterState::Failed(
+                "Plotter error: ALARM:1".to_string()
+            )))
+        );
+        cmdsend.send(PlotterCommand::Shutdown).unwrap();
+    }
+
+    #[test]
+    fn test_character_counting() {
+        let program: Vec<String> = (0..10)
+            .map(|idx| format!("G1 X{}.123 Y{}.456 F1200", idx, idx))
+            .collect();
+        // The simulated controller has a 64 byte buffer and a one line
+        // planner, so it only takes a couple of lines ahead.
+        for (rx_buffer, overflows) in [(64, false), (256, true)] {
+            let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+            cmdsend
+                .send(PlotterCommand::Connect(
+                    "sim://grbl?latency=20&buffer=1&rx=64".to_string(),
+                ))
+                .unwrap();
+            wait_for(&resprecv, |resp| {
+                *resp == PlotterResponse::State(PlotterState::Ready)
+            });
+            cmdsend
+                .send(PlotterCommand::SetStreaming(
+                    Streaming::CharacterCounting,
+                    rx_buffer,
+                ))
+                .unwrap();
+            cmdsend
+                .send(PlotterCommand::Program(Box::new(program.clone())))
+                .unwrap();
+            cmdsend.send(PlotterCommand::Run).unwrap();
+            let seen = wait_for(&resprecv, |resp| {
+                matches!(
+                    resp,
+                    PlotterResponse::State(PlotterState::Ready | PlotterState::Failed(_))
+                )
+            });
+            assert_eq!(
+                matches!(
+                    seen.last(),
+                    Some(PlotterResponse::State(PlotterState::Failed(_)))
+                ),
+                overflows,
+                "{:?}",
+                seen.last()
+            );
+            cmdsend.send(PlotterCommand::Shutdown).unwrap();
+            wait_for(&resprecv, |resp| {
+                *resp == PlotterResponse::State(PlotterState::Dead)
+            });
         }
+    }
+
+    #[test]
+    fn test_character_counting_short_lines() {
+        // Short lines pack a lot more than the ok counting backlog into the
+        // buffer.
+        let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+        cmdsend
+            .send(PlotterCommand::Connect(
+                "sim://grbl?latency=20&buffer=1&rx=128".to_string(),
+            ))
+            .unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Ready)
+        });
+        cmdsend
+            .send(PlotterCommand::SetStreaming(
+                Streaming::CharacterCounting,
+                128,
+            ))
+            .unwrap();
+        cmdsend
+            .send(PlotterCommand::Program(Box::new(
+                (0..40).map(|idx| format!("G1 X{}", idx)).collect(),
+            )))
+            .unwrap();
+        cmdsend.send(PlotterCommand::Run).unwrap();
+        let seen = wait_for(&resprecv, |resp| {
+            matches!(
+                resp,
+                PlotterResponse::State(PlotterState::Ready | PlotterState::Failed(_))
+            )
+        });
+        assert_eq!(
+            seen.last(),
+            Some(&PlotterResponse::State(PlotterState::Ready))
+        );
+        assert!(seen.iter().any(|resp| matches!(
+            resp,
+            PlotterResponse::State(PlotterState::Running(_, _, oks)) if *oks > MAX_OKS_BACKLOG as u32
+        )));
         cmdsend.send(PlotterCommand::Shutdown).unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Dead)
+        });
     }
 
     #[test]
-    fn test_early_termination() {
+    fn test_feed_hold() {
+        let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+        cmdsend
+            .send(PlotterCommand::Connect(
+                "sim://grbl?latency=50&buffer=4".to_string(),
+            ))
+            .unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Ready)
+        });
+        cmdsend
+            .send(PlotterCommand::Program(Box::new(
+                (1..=10).map(|idx| format!("G1 X{} F600", idx)).collect(),
+            )))
+            .unwrap();
+        cmdsend.send(PlotterCommand::Run).unwrap();
+        wait_for(&resprecv, |resp| {
+            matches!(resp, PlotterResponse::State(PlotterState::Running(6, _, _)))
+        });
+        // Held with the planner still full, and the reports say so.
+        cmdsend.send(PlotterCommand::Stop).unwrap();
+        let seen = wait_for(
+            &resprecv,
+            |resp| matches!(resp, PlotterResponse::Status(status) if status.state == MachineState::Hold),
+        );
+        let Some(PlotterResponse::Status(held)) = seen.last() else {
+            unreachable!()
+        };
+        assert!(held.position.is_some_and(|(x, _, _)| x < 5.), "{:?}", held);
+        assert_eq!(held.feed, Some(600.));
+
+        cmdsend.send(PlotterCommand::Run).unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Ready)
+        });
+        wait_for(&resprecv, |resp| {
+            matches!(resp, PlotterResponse::Status(status)
+                if status.state == MachineState::Idle && status.position == Some((10., 0., 0.)))
+        });
+        cmdsend.send(PlotterCommand::Shutdown).unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Dead)
+        });
+    }
+
+    #[test]
+    fn test_resume_from_hold() {
         let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
-        println!("Spawned");
         cmdsend
             .send(PlotterCommand::Connect(
-                "serial:///dev/ttyACM0@1000000".to_string(),
+                "sim://grbl?latency=50&buffer=4".to_string(),
             ))
             .unwrap();
-        std::thread::sleep(Duration::from_millis(1000));
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Ready)
+        });
+        let program = ["G91".to_string()]
+            .into_iter()
+            .chain((1..10).map(|_| "G1 X1 F600".to_string()))
+            .collect();
+        cmdsend
+            .send(PlotterCommand::Program(Box::new(program)))
+            .unwrap();
+        cmdsend.send(PlotterCommand::Run).unwrap();
+        wait_for(&resprecv, |resp| {
+            matches!(resp, PlotterResponse::State(PlotterState::Running(6, _, _)))
+        });
+        cmdsend.send(PlotterCommand::Stop).unwrap();
+        let seen = wait_for(
+            &resprecv,
+            |resp| matches!(resp, PlotterResponse::Status(status) if status.state == MachineState::Hold),
+        );
+        let Some(PlotterResponse::Status(held)) = seen.last() else {
+            unreachable!()
+        };
+        let (x, _, _) = held.position.unwrap();
+        // The moves buffered at the hold are dropped, so only the four
+        // relative moves from line 6 on get drawn.
+        cmdsend
+            .send(PlotterCommand::RunFrom(
+                6,
+                Box::new(vec!["G91".to_string()]),
+            ))
+            .unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Ready)
+        });
+        wait_for(&resprecv, |resp| {
+            matches!(resp, PlotterResponse::Status(status)
+                if status.state == MachineState::Idle && status.position == Some((x + 4., 0., 0.)))
+        });
+        cmdsend.send(PlotterCommand::Shutdown).unwrap();
+        wait_for(&resprecv, |resp| {
+            *resp == PlotterResponse::State(PlotterState::Dead)
+        });
+    }
+
+    #[test]
+    fn test_early_termination() {
+        let (cmdsend, resprecv) = PlotterConnection::spawn().unwrap();
+        println!("Spawned");
+        cmdsend
+            .send(PlotterCommand::Connect("sim://grbl".to_string()))
+            .unwrap();
         cmdsend
-            .send(PlotterCommand::Command("G28 X Y ;".to_string()))
+            .send(PlotterCommand::Command("$H ;".to_string()))
             .unwrap();
 
         cmdsend.send(PlotterCommand::Shutdown).unwrap();
@@ -1,6 +1,8 @@
 use glob::glob;
 use regex::Regex;
 
+use super::sim::SIM_PORT;
+
 pub fn scan_ports() -> Vec<String> {
     let ttyregex =
         Regex::new(r"^/dev/tty(ACM\d{1}|USB\d{1}|BOTAPLOT\d{1})").expect("Invalid regex");
@@ -17,5 +19,6 @@ pub fn scan_ports() -> Vec<String> {
             }
         }
     }
+    found_ports.push(SIM_PORT.to_string());
     found_ports
 }
@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+use super::modal::parse_words;
+use super::sender::{PlotterConnectionError, PlotterTransport};
+
+/// Always offered as a port, so there's something to plot to without
+/// hardware.
+pub const SIM_PORT: &str = "sim://grbl?latency=5";
+/// How many moves GRBL's planner holds.
+const DEFAULT_BUFFER: usize = 15;
+
+/// Which controller the simulator pretends to be.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimDialect {
+    Grbl,
+    Marlin,
+}
+
+/// An in-process stand in for a GRBL or Marlin controller, for dry runs and
+/// tests. It's set up with sim://grbl or sim://marlin, and these options:
+///
+/// * latency: how long (ms) each line takes to run. 0 by default.
+/// * buffer: how many lines fit in the planner before the oks slow down to
+///   the speed lines are run at. 15 by default.
+/// * error: answer every Nth line with an error instead of ok.
+/// * alarm: raise an alarm on the Nth line. Every line after it errors.
+/// * rx: the receive buffer size in bytes. Lines sent while it's full are
+///   lost, and get an error. Unlimited by default.
+///
+/// The GRBL one also takes the realtime commands: `?`, `!`, `~` and 0x18.
+pub struct SimTransport {
+    dialect: SimDialect,
+    latency: Duration,
+    buffer: usize,
+    error_every: Option<usize>,
+    alarm_at: Option<usize>,
+    alarmed: bool,
+    rx_buffer: Option<usize>,
+    /// The size of each line in the receive buffer, and when it leaves for
+    /// the planner.
+    receiving: VecDeque<(Instant, usize)>,
+    /// Each response, and when it's sent.
+    responses: VecDeque<(Instant, String)>,
+    /// When each line in the planner finishes running.
+    planner: VecDeque<Instant>,
+    /// When each line finishes running, and where the pen is then.
+    moves: VecDeque<(Instant, (f64, f64, f64))>,
+    /// When a feed hold started.
+    held: Option<Instant>,
+    lines: usize,
+    relative: bool,
+    position: (f64, f64, f64),
+    /// Where the pen is according to the last line to finish.
+    reported: (f64, f64, f64),
+    feed: f64,
+}
+
+impl SimTransport {
+    pub fn from_url(url: &url::Url) -> Result<SimTransport, PlotterConnectionError> {
+        let dialect = match url.host_str().unwrap_or("grbl") {
+            "" | "grbl" => SimDialect::Grbl,
+            "marlin" => SimDialect::Marlin,
+            other => {
+                return Err(PlotterConnectionError::ParseError(format!(
+                    "Unknown simulated controller '{}'",
+                    other
+                )));
+            }
+        };
+        let option = |name: &str| -> Result<Option<usize>, PlotterConnectionError> {
+            match url.query_pairs().find(|(key, _)| key == name) {
+                Some((_, value)) => Ok(Some(value.parse::<usize>()?)),
+                None => Ok(None),
+            }
+        };
+        let mut sim = SimTransport::new(dialect);
+        sim.latency = Duration::from_millis(option("latency")?.unwrap_or(0) as u64);
+        sim.buffer = option("buffer")?.unwrap_or(DEFAULT_BUFFER).max(1);
+        sim.error_every = option("error")?.filter(|every| *every > 0);
+        sim.alarm_at = option("alarm")?;
+        sim.rx_buffer = option("rx")?;
+        Ok(sim)
+    }
+
+    /// A controller that's just been reset, so it's sent its banner.
+    pub fn new(dialect: SimDialect) -> SimTransport {
+        let now = Instant::now();
+        let banner = match dialect {
+            SimDialect::Grbl => "Grbl 1.1h ['$' for help]",
+            SimDialect::Marlin => "start",
+        };
+        SimTransport {
+            dialect,
+            latency: Duration::ZERO,
+            buffer: DEFAULT_BUFFER,
+            error_every: None,
+            alarm_at: None,
+            alarmed: false,
+            rx_buffer: None,
+            receiving: VecDeque::new(),
+            responses: VecDeque::from([(now, banner.to_string()), (now, "ok".to_string())]),
+            planner: VecDeque::new(),
+            moves: VecDeque::new(),
+            held: None,
+            lines: 0,
+            relative: false,
+            position: (0., 0., 0.),
+            reported: (0., 0., 0.),
+            feed: 0.,
+        }
+    }
+
+    /// Where the pen is (X, Y, Z), once everything sent so far has run.
+    pub fn position(&self) -> (f64, f64, f64) {
+        self.position
+    }
+
+    fn error(&self, code: usize) -> String {
+        match self.dialect {
+            SimDialect::Grbl => format!("error:{}", code),
+            SimDialect::Marlin => format!("Error:{}", code),
+        }
+    }
+
+    /// Works out what the controller says to a line, and moves the pen.
+    fn respond(&mut self, line: &str) -> Vec<String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return vec!["ok".to_string()];
+        }
+        self.lines += 1;
+        if self.alarm_at == Some(self.lines) {
+            self.alarmed = true;
+            return vec!["ALARM:1".to_string()];
+        }
+        if self.alarmed {
+            // GRBL won't run anything until the alarm's cleared.
+            return vec![self.error(9)];
+        }
+        if self
+            .error_every
+            .is_some_and(|every| self.lines.is_multiple_of(every))
+        {
+            return vec![self.error(20)];
+        }
+        if let Some(command) = line.strip_prefix('$') {
+            // System commands, of which only homing moves anything.
+            let command = command.split(';').next().unwrap_or("").trim();
+            if command.eq_ignore_ascii_case("H") {
+                self.position = (0., 0., 0.);
+            }
+            return vec!["ok".to_string()];
+        }
+        let words = parse_words(line);
+        if self.dialect == SimDialect::Grbl && words.iter().any(|(_, value)| value.is_nan()) {
+            // GRBL wants a number after every letter, even in G28 X Y.
+            return vec![self.error(2)];
+        }
+        let word = |letter: char| {
+            words
+                .iter()
+                .find(|(found, value)| found.to_ascii_uppercase() == letter && !value.is_nan())
+                .map(|(_, value)| *value)
+        };
+        let code = word('G').map(|code| code as u32);
+        match code {
+            Some(90) => self.relative = false,
+            Some(91) => self.relative = true,
+            Some(28) => self.position = (0., 0., 0.),
+            _ => (),
+        }
+        // G92 says where the pen is now, rather than moving it.
+        let relative = self.relative && code != Some(92);
+        let moved = |axis: Option<f64>, from: f64| match (axis, relative) {
+            (Some(value), true) => from + value,
+            (Some(value), false) => value,
+            (None, _) => from,
+        };
+        if let Some(feed) = word('F') {
+            self.feed = feed;
+        }
+        let (x, y, z) = self.position;
+        self.position = (
+            moved(word('X'), x),
+            moved(word('Y'), y),
+            moved(word('Z'), z),
+        );
+        vec!["ok".to_string()]
+    }
+
+    /// A GRBL 1.1 status report, as of now.
+    fn status(&mut self, now: Instant) -> String {
+        let until = self.held.unwrap_or(now);
+        while let Some((done, position)) = self.moves.front().copied()
+            && done <= until
+        {
+            self.reported = position;
+            self.moves.pop_front();
+        }
+        let state = match (self.alarmed, self.held, self.moves.is_empty()) {
+            (true, _, _) => "Alarm",
+            (false, Some(_), _) => "Hold:0",
+            (false, None, false) => "Run",
+            (false, None, true) => "Idle",
+        };
+        let (x, y, z) = self.reported;
+        format!(
+            "<{}|MPos:{:.3},{:.3},{:.3}|FS:{:.0},0>",
+            state, x, y, z, self.feed
+        )
+    }
+
+    /// Pushes back everything that was due after a hold started by however
+    /// long it was held for.
+    fn release(&mut self, started: Instant, now: Instant) {
+        let held = now - started;
+        let shift = |when: &mut Instant| {
+            if *when > started {
+                *when += held;
+            }
+        };
+        self.receiving.iter_mut().for_each(|(when, _)| shift(when));
+        self.responses.iter_mut().for_each(|(when, _)| shift(when));
+        self.planner.iter_mut().for_each(shift);
+        self.moves.iter_mut().for_each(|(when, _)| shift(when));
+    }
+}
+
+impl PlotterTransport for SimTransport {
+    fn write_line(&mut self, buf: &str) -> io::Result<()> {
+        let now = Instant::now();
+        while self.receiving.front().is_some_and(|(gone, _)| *gone <= now) {
+            self.receiving.pop_front();
+        }
+        let queued: usize = self.receiving.iter().map(|(_, size)| size).sum();
+        if self
+            .rx_buffer
+            .is_some_and(|size| queued + buf.len() + 1 > size)
+        {
+            // Overflowed, so the line's lost.
+            let error = self.error(11);
+            let ready = self.receiving.back().map_or(now, |(gone, _)| *gone);
+            self.responses.push_back((ready, error));
+            return Ok(());
+        }
+        while self.planner.front().is_some_and(|done| *done <= now) {
+            self.planner.pop_front();
+        }
+        let start = self.planner.back().copied().unwrap_or(now).max(now);
+        // The ok comes once there's room in the planner, so when it's full
+        // that's when the oldest line in it finishes.
+        let ready = match self.planner.len() >= self.buffer {
+            true => self.planner.pop_front().unwrap_or(now),
+            false => now,
+        };
+        self.planner.push_back(start + self.latency);
+        self.receiving.push_back((ready, buf.len() + 1));
+        for response in self.respond(buf) {
+            self.responses.push_back((ready, response));
+        }
+        self.moves.push_back((start + self.latency, self.position));
+        Ok(())
+    }
+
+    fn write_realtime(&mut self, byte: u8) -> io::Result<()> {
+        if self.dialect != SimDialect::Grbl {
+            return Ok(());
+        }
+        let now = Instant::now();
+        match byte {
+            b'?' => {
+                // Reports skip the queue, so go out ahead of any oks that
+                // aren't due yet.
+                let status = self.status(now);
+                let due = self.held.unwrap_or(now);
+                let at = self
+                    .responses
+                    .iter()
+                    .position(|(ready, _)| *ready > due)
+                    .unwrap_or(self.responses.len());
+                self.responses.insert(at, (now, status));
+            }
+            b'!' => {
+                self.held.get_or_insert(now);
+            }
+            b'~' => {
+                if let Some(started) = self.held.take() {
+                    self.release(started, now);
+                }
+            }
+            0x18 => {
+                // Anything not run yet is lost, but GRBL keeps its position
+                // as long as it wasn't moving.
+                self.status(now);
+                let (latency, buffer, rx_buffer) = (self.latency, self.buffer, self.rx_buffer);
+                let position = self.reported;
+                *self = SimTransport::new(SimDialect::Grbl);
+                self.responses.pop_back();
+                (self.latency, self.buffer, self.rx_buffer) = (latency, buffer, rx_buffer);
+                (self.position, self.reported) = (position, position);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        match self.responses.pop_front() {
+            Some((ready, response)) => {
+                let now = Instant::now();
+                if ready > now {
+                    std::thread::sleep(ready - now);
+                }
+                buf.push_str(&response);
+                buf.push('\n');
+                Ok(response.len() + 1)
+            }
+            // Nothing is ever going to turn up.
+            None => Err(io::Error::from(ErrorKind::TimedOut)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn has_input(&mut self) -> io::Result<bool> {
+        let now = Instant::now();
+        Ok(self
+            .responses
+            .front()
+            .is_some_and(|(ready, _)| *ready <= now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(sim: &mut SimTransport) -> String {
+        let mut line = String::new();
+        sim.read_line(&mut line).expect("Nothing to read");
+        line.trim().to_string()
+    }
+
+    #[test]
+    fn test_sim_transport() {
+        let url = url::Url::parse("sim://grbl?latency=20&buffer=2&error=4&alarm=6").unwrap();
+        let mut sim = SimTransport::from_url(&url).expect("Failed to simulate");
+        assert!(read(&mut sim).starts_with("Grbl"));
+        assert_eq!(read(&mut sim), "ok");
+
+        let start = Instant::now();
+        for line in ["G90", "G0 X10 Y5", "G1 Z-2", "G91"] {
+            sim.write_line(line).unwrap();
+        }
+        assert_eq!(
+            (0..4).map(|_| read(&mut sim)).collect::<Vec<_>>(),
+            vec!["ok", "ok", "ok", "error:20"]
+        );
+        // With two lines' worth of planner, the third ok waits for the
+        // first line to run.
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(sim.position(), (10., 5., -2.));
+
+        for line in ["G0 X1", "G0 X1", "G0 Y1"] {
+            sim.write_line(line).unwrap();
+        }
+        assert_eq!(
+            (0..3).map(|_| read(&mut sim)).collect::<Vec<_>>(),
+            vec!["ok", "ALARM:1", "error:9"]
+        );
+        // The G91 errored, so the first move was absolute. The line that
+        // tripped the alarm, and anything after it, didn't move.
+        assert_eq!(sim.position(), (1., 5., -2.));
+        assert!(sim.read_line(&mut String::new()).is_err());
+
+        // GRBL homes with $H, and won't take axes without a number.
+        let mut sim = SimTransport::new(SimDialect::Grbl);
+        read(&mut sim);
+        read(&mut sim);
+        for line in ["G0 X10 Y5", "G28 X Y", "$H ; HOME"] {
+            sim.write_line(line).unwrap();
+        }
+        assert_eq!(
+            (0..3).map(|_| read(&mut sim)).collect::<Vec<_>>(),
+            vec!["ok", "error:2", "ok"]
+        );
+        assert_eq!(sim.position(), (0., 0., 0.));
+    }
+}
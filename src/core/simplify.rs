@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use geo::{Coord, LineString, MultiLineString};
+
+use super::config::SimplifyMethod;
+
+/// Distance from p to the segment a-b.
+fn segment_distance(p: &Coord<f64>, a: &Coord<f64>, b: &Coord<f64>) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0. {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / len2).clamp(0., 1.)
+    } else {
+        0.
+    };
+    ((p.x - (a.x + t * dx)).powi(2) + (p.y - (a.y + t * dy)).powi(2)).sqrt()
+}
+
+fn triangle_area(a: &Coord<f64>, b: &Coord<f64>, c: &Coord<f64>) -> f64 {
+    ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)).abs() / 2.
+}
+
+/// Douglas-Peucker: keeps the point furthest from the chord as long as it
+/// is further than the tolerance, and recurses on either side.
+fn douglas_peucker(points: &[Coord<f64>], tolerance: f64) -> Vec<Coord<f64>> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0usize, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut furthest = (0usize, 0.0f64);
+        for (offset, point) in points[(first + 1)..last].iter().enumerate() {
+            let distance = segment_distance(point, &points[first], &points[last]);
+            if distance > furthest.1 {
+                furthest = (first + 1 + offset, distance);
+            }
+        }
+        if furthest.1 > tolerance {
+            keep[furthest.0] = true;
+            stack.push((first, furthest.0));
+            stack.push((furthest.0, last));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+#[derive(PartialEq)]
+struct Candidate {
+    area: f64,
+    index: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so the BinaryHeap pops the smallest area first.
+        other
+            .area
+            .partial_cmp(&self.area)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Visvalingam-Whyatt: repeatedly drops the point that forms the smallest
+/// triangle with its neighbours, until every remaining triangle is at
+/// least tolerance² in area.
+fn visvalingam(points: &[Coord<f64>], tolerance: f64) -> Vec<Coord<f64>> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let threshold = tolerance * tolerance;
+    let count = points.len();
+    let mut prev: Vec<usize> = (0..count).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (0..count).map(|i| (i + 1).min(count - 1)).collect();
+    let mut removed = vec![false; count];
+    let mut areas = vec![f64::INFINITY; count];
+    let mut heap = BinaryHeap::new();
+    for i in 1..(count - 1) {
+        areas[i] = triangle_area(&points[i - 1], &points[i], &points[i + 1]);
+        heap.push(Candidate {
+            area: areas[i],
+            index: i,
+        });
+    }
+    while let Some(Candidate { area, index }) = heap.pop() {
+        if area >= threshold {
+            break;
+        }
+        // Stale entry, since we recalculate neighbours as we go.
+        if removed[index] || area != areas[index] {
+            continue;
+        }
+        removed[index] = true;
+        let (before, after) = (prev[index], next[index]);
+        next[before] = after;
+        prev[after] = before;
+        for neighbour in [before, after] {
+            if neighbour != 0 && neighbour != count - 1 {
+                areas[neighbour] = triangle_area(
+                    &points[prev[neighbour]],
+                    &points[neighbour],
+                    &points[next[neighbour]],
+                );
+                heap.push(Candidate {
+                    area: areas[neighbour],
+                    index: neighbour,
+                });
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(removed)
+        .filter_map(|(point, removed)| (!removed).then_some(*point))
+        .collect()
+}
+
+/// Simplifies every line with the given method, returning the simplified
+/// lines and how many vertices were removed. Closed rings that would
+/// collapse are left alone.
+pub fn simplify_lines(
+    lines: &MultiLineString<f64>,
+    method: &SimplifyMethod,
+    tolerance: f64,
+) -> (MultiLineString<f64>, usize) {
+    if *method == SimplifyMethod::None || tolerance <= 0. {
+        return (lines.clone(), 0);
+    }
+    let mut removed = 0usize;
+    let simplified = lines
+        .0
+        .iter()
+        .map(|line| {
+            let points = match method {
+                SimplifyMethod::None => line.0.clone(),
+                SimplifyMethod::DouglasPeucker => douglas_peucker(&line.0, tolerance),
+                SimplifyMethod::Visvalingam => visvalingam(&line.0, tolerance),
+            };
+            if line.is_closed() && points.len() < 4 {
+                return line.clone();
+            }
+            removed += line.0.len() - points.len();
+            LineString::new(points)
+        })
+        .collect();
+    (MultiLineString::new(simplified), removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::coord;
+
+    fn wiggly_line() -> LineString<f64> {
+        LineString::new(
+            (0..=100)
+                .map(|i| coord! {x: i as f64, y: if i % 2 == 0 { 0. } else { 0.01 }})
+                .collect::<Vec<Coord<f64>>>(),
+        )
+    }
+
+    #[test]
+    fn test_douglas_peucker() {
+        let lines = MultiLineString::new(vec![wiggly_line()]);
+        let (simplified, removed) = simplify_lines(&lines, &SimplifyMethod::DouglasPeucker, 0.05);
+        assert_eq!(simplified.0[0].0.len(), 2);
+        assert_eq!(removed, 99);
+        let (_, removed) = simplify_lines(&lines, &SimplifyMethod::DouglasPeucker, 0.001);
+        assert_eq!(removed, 0);
+    }
+
+    #[test]
+    fn test_visvalingam() {
+        let lines = MultiLineString::new(vec![LineString::new(vec![
+            coord! {x: 0., y: 0.},
+            coord! {x: 5., y: 0.01},
+            coord! {x: 10., y: 0.},
+            coord! {x: 10., y: 10.},
+        ])]);
+        let (simplified, removed) = simplify_lines(&lines, &SimplifyMethod::Visvalingam, 0.5);
+        assert_eq!(removed, 1);
+        assert_eq!(
+            simplified.0[0].0,
+            vec![
+                coord! {x: 0., y: 0.},
+                coord! {x: 10., y: 0.},
+                coord! {x: 10., y: 10.}
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rings_do_not_collapse() {
+        let ring = LineString::new(vec![
+            coord! {x: 0., y: 0.},
+            coord! {x: 0.01, y: 0.},
+            coord! {x: 0.01, y: 0.01},
+            coord! {x: 0., y: 0.},
+        ]);
+        let lines = MultiLineString::new(vec![ring.clone()]);
+        let (simplified, removed) = simplify_lines(&lines, &SimplifyMethod::DouglasPeucker, 1.);
+        assert_eq!(removed, 0);
+        assert_eq!(simplified.0[0], ring);
+    }
+}
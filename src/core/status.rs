@@ -0,0 +1,152 @@
+use std::fmt;
+
+/// What GRBL says it's doing, from the start of a status report.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MachineState {
+    Idle,
+    Run,
+    Hold,
+    Jog,
+    Alarm,
+    Door,
+    Check,
+    Home,
+    Sleep,
+    Unknown(String),
+}
+
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineState::Unknown(state) => write!(f, "{}", state),
+            state => write!(f, "{:?}", state),
+        }
+    }
+}
+
+/// A parsed `?` status report.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineStatus {
+    pub state: MachineState,
+    /// Work position (X, Y, Z), the coordinates the program is in. Worked
+    /// out from the machine position and work offset if need be, or just
+    /// the machine position if there's no offset to go on.
+    pub position: Option<(f64, f64, f64)>,
+    /// The work coordinate offset (G92/G10), if the report had one.
+    pub wco: Option<(f64, f64, f64)>,
+    /// The machine position, when position still needs the offset taking
+    /// off it.
+    machine: Option<(f64, f64, f64)>,
+    /// Current feed rate, in mm/min.
+    pub feed: Option<f64>,
+}
+
+impl MachineStatus {
+    /// Parses a report like `<Run|MPos:1.000,2.000,0.000|FS:1200,0>` (GRBL
+    /// 1.1) or `<Idle,MPos:1.000,2.000,0.000,WPos:...>` (GRBL 0.9). Anything
+    /// that isn't a report gives None.
+    pub fn parse(line: &str) -> Option<MachineStatus> {
+        let body = line.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let state = body.split(['|', ',']).next()?;
+        // Substates like Hold:0 or Door:1 are dropped.
+        let state = match state.split(':').next().unwrap_or(state) {
+            "Idle" => MachineState::Idle,
+            "Run" => MachineState::Run,
+            "Hold" => MachineState::Hold,
+            "Jog" => MachineState::Jog,
+            "Alarm" => MachineState::Alarm,
+            "Door" => MachineState::Door,
+            "Check" => MachineState::Check,
+            "Home" => MachineState::Home,
+            "Sleep" => MachineState::Sleep,
+            other => MachineState::Unknown(other.to_string()),
+        };
+        let xyz = |key: &str| {
+            field(body, key)
+                .filter(|values| values.len() >= 3)
+                .map(|values| (values[0], values[1], values[2]))
+        };
+        let work = xyz("WPos:");
+        let wco = xyz("WCO:");
+        let machine = xyz("MPos:").filter(|_| work.is_none());
+        let feed = field(body, "FS:")
+            .or(field(body, "F:"))
+            .and_then(|values| values.first().copied());
+        let mut status = MachineStatus {
+            state,
+            position: work.or(machine),
+            wco: None,
+            machine,
+            feed,
+        };
+        status.offset_by(&mut wco.clone());
+        status.wco = wco;
+        Some(status)
+    }
+
+    /// Takes the work offset off the machine position. GRBL only sends the
+    /// offset every so often, so last holds the latest one seen: it's
+    /// updated from this report, or used if this report hasn't got one.
+    pub fn offset_by(&mut self, last: &mut Option<(f64, f64, f64)>) {
+        if self.wco.is_some() {
+            *last = self.wco;
+        }
+        if let (Some((x, y, z)), Some((dx, dy, dz))) = (self.machine, *last) {
+            self.position = Some((x - dx, y - dy, z - dz));
+        }
+    }
+}
+
+/// The numbers following a key, up to the next thing that isn't one.
+fn field(body: &str, key: &str) -> Option<Vec<f64>> {
+    let start = body
+        .match_indices(key)
+        .find(|(idx, _)| *idx == 0 || body[..*idx].ends_with(['|', ',']))?
+        .0;
+    let values: Vec<f64> = body[start + key.len()..]
+        .split(['|', ','])
+        .map_while(|value| value.parse::<f64>().ok())
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let status = MachineStatus::parse("<Run|MPos:10.000,5.500,-1.000|FS:1200,0|WCO:0,0,0>")
+            .expect("Failed to parse");
+        assert_eq!(status.state, MachineState::Run);
+        assert_eq!(status.position, Some((10., 5.5, -1.)));
+        assert_eq!(status.feed, Some(1200.));
+
+        // The offset comes and goes, so the last one is kept.
+        let mut wco = None;
+        let mut status = MachineStatus::parse("<Idle|MPos:15.000,5.000,0.000|WCO:10,-5,0>")
+            .expect("Failed to parse");
+        assert_eq!(status.position, Some((5., 10., 0.)));
+        status.offset_by(&mut wco);
+        assert_eq!(wco, Some((10., -5., 0.)));
+        let mut status =
+            MachineStatus::parse("<Idle|MPos:20.000,5.000,0.000>").expect("Failed to parse");
+        assert_eq!(status.position, Some((20., 5., 0.)));
+        status.offset_by(&mut wco);
+        assert_eq!(status.position, Some((10., 10., 0.)));
+
+        let status = MachineStatus::parse("<Hold:0|WPos:1.000,2.000,3.000|F:500>\r\n")
+            .expect("Failed to parse");
+        assert_eq!(status.state, MachineState::Hold);
+        assert_eq!(status.position, Some((1., 2., 3.)));
+        assert_eq!(status.feed, Some(500.));
+
+        let status = MachineStatus::parse("<Alarm,MPos:0.000,0.000,0.000,WPos:1.000,1.000,1.000>")
+            .expect("Failed to parse");
+        assert_eq!(status.state, MachineState::Alarm);
+        assert_eq!(status.position, Some((1., 1., 1.)));
+        assert_eq!(status.feed, None);
+
+        assert_eq!(MachineStatus::parse("ok"), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::sender::PlotterTransport;
+
+/// How many times to try getting a dropped connection back.
+const RECONNECT_ATTEMPTS: u32 = 3;
+/// How long to wait before the first reconnect. Each retry waits longer.
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+/// A plotter on the network, like an ESP32 GRBL bridge or a ser2net port.
+/// Dropped connections are reopened if nothing's waiting on an answer, and
+/// telnet ones have their option negotiation stripped out and refused.
+pub struct TcpTransport {
+    address: String,
+    timeout: Duration,
+    telnet: bool,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    /// Lines sent that haven't had an ok or error back yet.
+    unanswered: usize,
+}
+
+impl TcpTransport {
+    /// Connects to host:port, giving up on connects, reads and writes that
+    /// take longer than timeout.
+    pub fn connect(address: &str, timeout: Duration, telnet: bool) -> io::Result<TcpTransport> {
+        let (reader, writer) = open(address, timeout)?;
+        Ok(TcpTransport {
+            address: address.to_string(),
+            timeout,
+            telnet,
+            reader,
+            writer,
+            unanswered: 0,
+        })
+    }
+
+    /// Reopens a dropped connection, unless there are lines still waiting on
+    /// an answer. Those may or may not have run, so carrying on could skip
+    /// or double up moves, and it's up to the operator to resume instead.
+    fn recover(&mut self, err: io::Error) -> io::Result<()> {
+        if self.unanswered > 0 {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                format!(
+                    "Lost {} with {} line(s) unanswered: {}",
+                    self.address, self.unanswered, err
+                ),
+            ));
+        }
+        self.reconnect()
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        let mut last_err = io::Error::from(ErrorKind::NotConnected);
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            eprintln!("Reconnecting to {} (attempt {}).", self.address, attempt);
+            match open(&self.address, self.timeout) {
+                Ok((reader, writer)) => {
+                    self.reader = reader;
+                    self.writer = writer;
+                    return Ok(());
+                }
+                Err(err) => {
+                    last_err = err;
+                    std::thread::sleep(RECONNECT_DELAY * attempt);
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self.writer.write_all(bytes) {
+            Err(err) if is_dropped(&err) => {
+                self.recover(err)?;
+                self.writer.write_all(bytes)
+            }
+            result => result,
+        }
+    }
+
+    /// Pulls telnet commands out of a line, refusing any options offered.
+    fn strip_telnet(&mut self, line: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(line.len());
+        let mut bytes = line.into_iter();
+        while let Some(byte) = bytes.next() {
+            if byte != IAC {
+                data.push(byte);
+                continue;
+            }
+            match bytes.next() {
+                Some(IAC) => data.push(IAC),
+                Some(verb @ (DO | DONT | WILL | WONT)) => {
+                    if let Some(option) = bytes.next() {
+                        let reply = match verb {
+                            DO => WONT,
+                            WILL => DONT,
+                            _ => continue,
+                        };
+                        self.writer.write_all(&[IAC, reply, option])?;
+                        self.writer.flush()?;
+                    }
+                }
+                Some(SB) => {
+                    let mut previous = SB;
+                    for byte in bytes.by_ref() {
+                        if previous == IAC && byte == SE {
+                            break;
+                        }
+                        previous = byte;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(data)
+    }
+}
+
+fn open(
+    address: &str,
+    timeout: Duration,
+) -> io::Result<(BufReader<TcpStream>, BufWriter<TcpStream>)> {
+    let mut last_err = io::Error::new(ErrorKind::NotFound, format!("Can't resolve {}", address));
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                stream.set_nodelay(true)?;
+                return Ok((BufReader::new(stream.try_clone()?), BufWriter::new(stream)));
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+/// Whether the connection is gone (rather than just slow), so it's worth
+/// reconnecting.
+fn is_dropped(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+impl PlotterTransport for TcpTransport {
+    fn write_line(&mut self, buf: &str) -> io::Result<()> {
+        let line = buf.to_owned() + "\n";
+        self.write_bytes(line.as_bytes())?;
+        self.unanswered += 1;
+        Ok(())
+    }
+
+    fn write_realtime(&mut self, byte: u8) -> io::Result<()> {
+        // Telnet would need a 255 doubled, but no realtime command is that.
+        self.write_bytes(&[byte])?;
+        self.flush()
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut line = Vec::new();
+        let count = match self.reader.read_until(b'\n', &mut line) {
+            // The other end hung up, so there's nothing more coming on this
+            // connection. Open a new one and wait on that instead.
+            Ok(0) => {
+                self.recover(io::Error::from(ErrorKind::UnexpectedEof))?;
+                self.reader.read_until(b'\n', &mut line)?
+            }
+            Err(err) if is_dropped(&err) => {
+                self.recover(err)?;
+                self.reader.read_until(b'\n', &mut line)?
+            }
+            result => result?,
+        };
+        if count == 0 {
+            return Ok(0);
+        }
+        if self.telnet {
+            line = self.strip_telnet(line)?;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let answer = text.trim();
+        if answer.starts_with("ok") || answer.starts_with("error") {
+            self.unanswered = self.unanswered.saturating_sub(1);
+        } else if answer.starts_with("Grbl") {
+            // A reset throws away everything it was sent.
+            self.unanswered = 0;
+        }
+        buf.push_str(&text);
+        // Never report an empty read for a line of telnet noise, as that
+        // means EOF.
+        Ok(text.len().max(1))
+    }
+
+    fn has_input(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+        let stream = self.reader.get_ref();
+        stream.set_nonblocking(true)?;
+        let peeked = stream.peek(&mut [0u8; 1]);
+        stream.set_nonblocking(false)?;
+        match peeked {
+            // A hang up counts, so read_line can deal with it.
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) if is_dropped(&err) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.flush() {
+            Err(err) if is_dropped(&err) => {
+                self.recover(err)?;
+                self.writer.flush()
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+        let address = listener.local_addr().unwrap().to_string();
+        // A stand in bridge. The first connection offers to echo, says ok to
+        // a line, then hangs up. The second greets like GRBL does.
+        let bridge = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            for greeting in [&[IAC, WILL, 1][..], &b"Grbl 1.1h ['$' for help]\n"[..]] {
+                let (mut stream, _) = listener.accept().expect("Failed to accept");
+                stream.write_all(greeting).unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                received.push(line);
+                stream.write_all(b"ok\n").unwrap();
+                if received.len() == 1 {
+                    let mut refusal = [0u8; 3];
+                    reader.read_exact(&mut refusal).unwrap();
+                    assert_eq!(refusal, [IAC, DONT, 1]);
+                }
+            }
+            received
+        });
+        let mut transport = TcpTransport::connect(&address, Duration::from_secs(5), true)
+            .expect("Failed to connect");
+        let mut line = String::new();
+        transport.write_line("G0 X1").unwrap();
+        transport.flush().unwrap();
+        transport.read_line(&mut line).unwrap();
+        assert_eq!(line, "ok\n");
+        // The bridge hung up, so this waits on a new connection.
+        line.clear();
+        transport.read_line(&mut line).unwrap();
+        assert!(line.starts_with("Grbl"), "{}", line);
+        line.clear();
+        transport.write_line("G0 X2").unwrap();
+        transport.flush().unwrap();
+        transport.read_line(&mut line).unwrap();
+        assert_eq!(line, "ok\n");
+        assert_eq!(bridge.join().unwrap(), vec!["G0 X1\n", "G0 X2\n"]);
+    }
+
+    #[test]
+    fn test_tcp_drop_in_flight() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+        let address = listener.local_addr().unwrap().to_string();
+        // Takes a line and hangs up without answering it.
+        let bridge = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("Failed to accept");
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            line
+        });
+        let mut transport = TcpTransport::connect(&address, Duration::from_secs(5), false)
+            .expect("Failed to connect");
+        transport.write_line("G1 X1").unwrap();
+        transport.flush().unwrap();
+        assert_eq!(bridge.join().unwrap(), "G1 X1\n");
+        let mut line = String::new();
+        let err = transport
+            .read_line(&mut line)
+            .expect_err("Reconnected with a line in flight");
+        assert_eq!(err.kind(), ErrorKind::ConnectionAborted);
+    }
+}
@@ -0,0 +1,165 @@
+use geo::{Geometry, LineString, coord};
+use tera::Tera;
+use uuid::Uuid;
+
+use super::config::PostOptions;
+use super::machine::{MachineConfig, MachineVariant};
+use super::post::post;
+use super::post_context::{JobBounds, job_context, set_pen_context};
+use super::project::{BAPGeometry, GeometryKind, KeepdownStrategy, PenDetail, Project};
+
+/// The sections post() renders, so every G-code machine needs them.
+pub const REQUIRED_SECTIONS: [&str; 10] = [
+    "prelude",
+    "epilog",
+    "penup",
+    "pendown",
+    "moveto",
+    "lineto",
+    "toolchange",
+    "penup_skim",
+    "pendown_skim",
+    "pendrop",
+];
+
+/// Problems with a machine's post templates, and what a short test path
+/// posts as with them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateLint {
+    /// (section, problem) pairs.
+    pub issues: Vec<(String, String)>,
+    /// The posted test path, or why it wouldn't post.
+    pub sample: Result<Vec<String>, String>,
+}
+
+impl TemplateLint {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty() && self.sample.is_ok()
+    }
+
+    pub fn issues_for<'a>(&'a self, section: &'a str) -> impl Iterator<Item = &'a String> {
+        self.issues
+            .iter()
+            .filter(move |(name, _)| name == section)
+            .map(|(_, issue)| issue)
+    }
+}
+
+/// A 20mm square with a diagonal through it, 10mm in from the origin.
+fn sample_project(machine: &MachineConfig) -> Project {
+    let mut project = Project::new();
+    project.set_machine(Some(machine.clone()));
+    project.set_origin(&Some((0., 40.)));
+    for points in [
+        vec![
+            coord! {x: 10., y: 10.},
+            coord! {x: 30., y: 10.},
+            coord! {x: 30., y: 30.},
+            coord! {x: 10., y: 30.},
+            coord! {x: 10., y: 10.},
+        ],
+        vec![coord! {x: 10., y: 10.}, coord! {x: 30., y: 30.}],
+    ] {
+        project.plot_geometry.push(BAPGeometry {
+            pen_uuid: Uuid::new_v4(),
+            name: "Test path".to_string(),
+            geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(points))),
+            keepdown_strategy: KeepdownStrategy::None,
+            pinned: false,
+            marks: false,
+        });
+    }
+    project
+}
+
+/// Checks the required sections are there, and that every section parses
+/// and renders with a sample of everything post() might put in its context.
+/// Then posts a short test path, so there's something to look at. HPGL
+/// machines don't post through the templates, so none are required.
+pub fn lint_templates(machine: &MachineConfig) -> TemplateLint {
+    let is_hpgl = machine.variant() == MachineVariant::HPGL;
+    let mut issues: Vec<(String, String)> = REQUIRED_SECTIONS
+        .iter()
+        .filter(|section| !is_hpgl && !machine.has_post_section(section))
+        .map(|section| (section.to_string(), "Missing section".to_string()))
+        .collect();
+    let project = sample_project(machine);
+    let mut context = job_context(&project, 2, &JobBounds::default());
+    set_pen_context(&mut context, &project, &PenDetail::default(), Some(0));
+    if let Some(height) = machine.skim() {
+        context.insert("skim", &height);
+    }
+    if let Some(pen_z) = machine.pen_z() {
+        context.insert("zmm", &pen_z.travel);
+    }
+    context.insert("xmm", &10.);
+    context.insert("ymm", &10.);
+    context.insert("imm", &5.);
+    context.insert("jmm", &0.);
+    context.insert("rmm", &5.);
+    context.insert("feedrate", &machine.feedrate());
+    for (section, template) in machine.get_post_template() {
+        let mut tera = Tera::default();
+        let rendered = tera
+            .add_raw_template(&section, &template)
+            .and_then(|_| tera.render(&section, &context));
+        if let Err(err) = rendered {
+            issues.push((section, format!("{:#}", anyhow::Error::from(err))));
+        }
+    }
+    for slot in machine.tool_slots() {
+        for (name, sequence) in [("grab", &slot.grab), ("release", &slot.release)] {
+            if let Err(err) = Tera::one_off(sequence, &context, false) {
+                issues.push((
+                    format!("T{} {}", slot.tool_id, name),
+                    format!("{:#}", anyhow::Error::from(err)),
+                ));
+            }
+        }
+    }
+    let sample = post(&project, &PostOptions::default())
+        .map(|(program, _summary)| program)
+        .map_err(|err| format!("{:#}", err));
+    TemplateLint { issues, sample }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_templates() {
+        let machine = MachineConfig::default();
+        let lint = lint_templates(&machine);
+        assert!(lint.is_clean(), "{:?}", lint);
+        assert!(lint.sample.unwrap().len() > 10);
+
+        let mut machine = MachineConfig::default();
+        let mut templates: Vec<(String, String)> = machine
+            .get_post_template()
+            .into_iter()
+            .filter(|(section, _)| section != "pendrop")
+            .collect();
+        for (section, template) in templates.iter_mut() {
+            if section == "moveto" {
+                *template = "G0 X{{xm}} Y{{ymm".to_string();
+            }
+            if section == "lineto" {
+                *template = "G1 X{{xmn}} Y{{ymm}}".to_string();
+            }
+        }
+        machine.set_post_template(&templates);
+        let lint = lint_templates(&machine);
+        assert_eq!(lint.issues_for("pendrop").count(), 1);
+        assert_eq!(lint.issues_for("moveto").count(), 1);
+        assert_eq!(lint.issues_for("lineto").count(), 1);
+        assert_eq!(lint.issues_for("prelude").count(), 0);
+        assert!(lint.sample.is_err());
+
+        let mut machine = MachineConfig::default();
+        machine.set_variant(MachineVariant::HPGL);
+        machine.set_post_template(&vec![]);
+        let lint = lint_templates(&machine);
+        assert!(lint.is_clean(), "{:?}", lint);
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+use geo::{BooleanOps, Geometry, LineString, MultiLineString, Rect, coord};
+
+use super::commands::{ApplicationStateChangeMsg, TileConfig, TileTarget};
+use super::export::export_pieces;
+use super::post::post;
+use super::project::{BAPGeometry, GeometryKind, KeepdownStrategy, PenDetail, Project};
+
+/// How far (mm) each arm of a registration cross reaches from its center.
+const MARK_ARM: f64 = 5.;
+
+/// The tiles covering the extents, row by row from the top left, as
+/// (row, column, rect) in project coordinates. Each tile is size big, and
+/// overlaps its neighbours by overlap. The grid is centered on the extents,
+/// so the outer tiles get even margins.
+pub fn tile_grid(
+    extents: &Rect<f64>,
+    size: (f64, f64),
+    overlap: f64,
+) -> AnyResult<Vec<(usize, usize, Rect<f64>)>> {
+    let (width, height) = size;
+    if overlap < 0. || overlap >= width.min(height) {
+        return Err(anyhow!(
+            "Overlap of {:.1}mm doesn't fit in {:.1}x{:.1}mm tiles",
+            overlap,
+            width,
+            height
+        ));
+    }
+    let count = |span: f64, tile: f64| -> usize {
+        if span <= tile {
+            1
+        } else {
+            ((span - overlap) / (tile - overlap)).ceil() as usize
+        }
+    };
+    let (rows, columns) = (
+        count(extents.height(), height),
+        count(extents.width(), width),
+    );
+    let origin = coord! {
+        x: extents.center().x - (columns as f64 * (width - overlap) + overlap) / 2.,
+        y: extents.center().y - (rows as f64 * (height - overlap) + overlap) / 2.,
+    };
+    let mut tiles = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let min = coord! {
+                x: origin.x + column as f64 * (width - overlap),
+                y: origin.y + row as f64 * (height - overlap),
+            };
+            tiles.push((
+                row,
+                column,
+                Rect::new(min, coord! {x: min.x + width, y: min.y + height}),
+            ));
+        }
+    }
+    Ok(tiles)
+}
+
+/// Crosses half the overlap in from each corner of the tile. That's the
+/// middle of the strip shared with the neighbouring tile, so its crosses
+/// are in the same spots.
+fn registration_marks(tile: &Rect<f64>, overlap: f64) -> MultiLineString<f64> {
+    let inset = overlap / 2.;
+    let mut crosses = Vec::new();
+    for x in [tile.min().x + inset, tile.max().x - inset] {
+        for y in [tile.min().y + inset, tile.max().y - inset] {
+            crosses.push(LineString::new(vec![
+                coord! {x: x - MARK_ARM, y: y},
+                coord! {x: x + MARK_ARM, y: y},
+            ]));
+            crosses.push(LineString::new(vec![
+                coord! {x: x, y: y - MARK_ARM},
+                coord! {x: x, y: y + MARK_ARM},
+            ]));
+        }
+    }
+    MultiLineString::new(crosses)
+}
+
+/// Splits the project into one project per tile, named like `r1c2`. Each
+/// has its geometry clipped to the tile and moved to the bottom left of the
+/// bed (or sheet), ready to post. Tiles with nothing on them are skipped.
+pub fn tile_projects(project: &Project, config: &TileConfig) -> AnyResult<Vec<(String, Project)>> {
+    let machine = project.machine().ok_or(anyhow!("Machine is not set."))?;
+    let size = match config.target {
+        TileTarget::Machine => machine.bed_size(),
+        TileTarget::Paper => project.paper.oriented_dimensions(),
+    };
+    let mark_pen = project
+        .pens
+        .first()
+        .cloned()
+        .unwrap_or(PenDetail::default());
+    let mut tiles = Vec::new();
+    for (row, column, rect) in tile_grid(&project.calc_extents(), size, config.overlap)? {
+        let clip = rect.to_polygon();
+        let mut tile = project.clone();
+        tile.plot_geometry = project
+            .plot_geometry
+            .iter()
+            .filter_map(|geometry| {
+                let lines = clip.clip(&geometry.lines(), false);
+                if lines.0.is_empty() {
+                    return None;
+                }
+                let lines = Geometry::MultiLineString(lines);
+                Some(BAPGeometry {
+                    geometry: match geometry.geometry {
+                        GeometryKind::Stroke(_) => GeometryKind::Stroke(lines),
+                        GeometryKind::Hatch(_) => GeometryKind::Hatch(lines),
+                    },
+                    ..geometry.clone()
+                })
+            })
+            .collect();
+        if tile.plot_geometry.is_empty() {
+            continue;
+        }
+        if config.registration_marks {
+            tile.plot_geometry.push(BAPGeometry {
+                pen_uuid: mark_pen.identity,
+                name: "Registration marks".to_string(),
+                geometry: GeometryKind::Stroke(Geometry::MultiLineString(
+                    clip.clip(&registration_marks(&rect, config.overlap), false),
+                )),
+                keepdown_strategy: KeepdownStrategy::None,
+                pinned: false,
+                marks: false,
+            });
+        }
+        // Project coordinates are Y down, so the tile's min corner is its top
+        // left, and the bottom left ends up at (0, height).
+        for geometry in tile.plot_geometry.iter_mut() {
+            geometry.translate_mut(-rect.min().x, -rect.min().y);
+        }
+        tile.set_origin(&Some((0., size.1)));
+        tile.set_program(None);
+        tile.regenerate_extents();
+        tiles.push((format!("r{}c{}", row + 1, column + 1), tile));
+    }
+    Ok(tiles)
+}
+
+impl super::ApplicationCore {
+    /// Posts every tile as its own job, next to path.
+    pub fn handle_export_tiles(&mut self, path: &Path, config: &TileConfig) {
+        let exported = tile_projects(&self.project, config).and_then(|tiles| {
+            let pieces = tiles
+                .iter()
+                .map(|(name, tile)| {
+                    post(tile, &self.config.post_options)
+                        .map(|(program, _)| (name.clone(), program))
+                })
+                .collect::<AnyResult<Vec<(String, Vec<String>)>>>()?;
+            export_pieces(path, &pieces)
+        });
+        match exported {
+            Ok(written) => self.yolo_app_state_change(ApplicationStateChangeMsg::ProgressMessage {
+                message: format!("Exported {} tile(s)", written.len()),
+                percentage: 100,
+            }),
+            Err(err) => self.yolo_app_state_change(ApplicationStateChangeMsg::Error(format!(
+                "Failed to export tiles to {}! Err:{}",
+                path.as_os_str().to_string_lossy(),
+                err
+            ))),
+        }
+        self.ctx.request_repaint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::machine::MachineConfig;
+
+    #[test]
+    fn test_tile_projects() {
+        let extents = Rect::new(coord! {x: 0., y: 0.}, coord! {x: 250., y: 90.});
+        let grid = tile_grid(&extents, (100., 100.), 10.).expect("Failed to tile");
+        assert_eq!(grid.len(), 3);
+        // Three tiles span 280mm, so there's 15mm spare either side.
+        assert_eq!(grid[0].2.min(), coord! {x: -15., y: -5.});
+        assert_eq!(grid[2].2.min().x, 165.);
+        assert!(tile_grid(&extents, (100., 100.), 100.).is_err());
+
+        let mut project = Project::new();
+        let mut machine = MachineConfig::default();
+        machine.set_limits((100., 100.));
+        project.set_machine(Some(machine));
+        let pen = PenDetail::default();
+        project.pens.push(pen.clone());
+        project.plot_geometry.push(BAPGeometry {
+            pen_uuid: pen.identity,
+            name: "Long line".to_string(),
+            geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(vec![
+                coord! {x: 0., y: 50.},
+                coord! {x: 150., y: 50.},
+            ]))),
+            keepdown_strategy: KeepdownStrategy::None,
+            pinned: false,
+            marks: false,
+        });
+        let config = TileConfig {
+            registration_marks: false,
+            ..TileConfig::default()
+        };
+        let tiles = tile_projects(&project, &config).expect("Failed to tile");
+        assert_eq!(
+            tiles
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["r1c1", "r1c2"]
+        );
+        // The tiles span 190mm from x = -20, so the second starts at 70mm
+        // and gets the last 80mm of the line.
+        let extents = tiles[1].1.extents();
+        assert!(extents.min().x.abs() < 1e-9);
+        assert!((extents.max().x - 80.).abs() < 1e-9);
+        assert_eq!(tiles[1].1.origin(), Some((0., 100.)));
+
+        let tiles = tile_projects(&project, &TileConfig::default()).expect("Failed to tile");
+        assert_eq!(tiles[0].1.plot_geometry.len(), 2);
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::Result as AnyResult;
+use anyhow::anyhow;
+use geo::{Coord, Rect, Translate};
+use nalgebra::{Point2, Vector2};
+
+use super::post::{GeometryToMultiLineString, bed_transform};
+use super::project::{PenDetail, Project};
+
+/// Points this close to an edge still count as inside.
+const EDGE_TOLERANCE: f64 = 1e-6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViolationKind {
+    MachineLimits,
+    Paper,
+}
+
+impl std::fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViolationKind::MachineLimits => write!(f, "the machine limits"),
+            ViolationKind::Paper => write!(f, "the paper"),
+        }
+    }
+}
+
+/// A geometry with segments that leave the machine limits or the paper.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// Index into the project's plot_geometry.
+    pub geometry: usize,
+    pub name: String,
+    pub kind: ViolationKind,
+    /// How many segments are (at least partly) outside.
+    pub segments: usize,
+    /// The first offending point, in machine coordinates.
+    pub first: (f64, f64),
+}
+
+/// Why the post refused to run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} problem(s) with the plot geometry:",
+            self.violations.len()
+        )?;
+        for violation in self.violations.iter().take(5) {
+            write!(
+                f,
+                "\n  {}: {} segment(s) outside {}, first at X{:.1} Y{:.1}",
+                violation.name,
+                violation.segments,
+                violation.kind,
+                violation.first.0,
+                violation.first.1
+            )?;
+        }
+        if self.violations.len() > 5 {
+            write!(f, "\n  ...and {} more", self.violations.len() - 5)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+fn contains(rect: &Rect<f64>, point: &Coord<f64>) -> bool {
+    point.x >= rect.min().x - EDGE_TOLERANCE
+        && point.x <= rect.max().x + EDGE_TOLERANCE
+        && point.y >= rect.min().y - EDGE_TOLERANCE
+        && point.y <= rect.max().y + EDGE_TOLERANCE
+}
+
+/// Checks every segment of the plot geometry, on the bed, against the
+/// machine limits and the oriented paper. Both are rectangles with a corner
+/// at the bed's bottom left, so a segment is inside as long as both its
+/// ends are. The limits are checked where the carriage goes, so with the
+/// pen's tool offset, the same as the post.
+pub fn validate(project: &Project) -> AnyResult<Vec<Violation>> {
+    let machine = project.machine().ok_or(anyhow!("Invalid machine"))?;
+    let tx = bed_transform(project)?;
+    let to_machine = machine.bed_to_machine();
+    let to_bed = to_machine.inverse();
+    let (limit_x, limit_y) = machine.bed_size();
+    let (paper_x, paper_y) = project.paper.oriented_dimensions();
+    let bounds = [
+        (
+            ViolationKind::MachineLimits,
+            Rect::new(
+                Coord { x: 0., y: 0. },
+                Coord {
+                    x: limit_x,
+                    y: limit_y,
+                },
+            ),
+        ),
+        (
+            ViolationKind::Paper,
+            Rect::new(
+                Coord { x: 0., y: 0. },
+                Coord {
+                    x: paper_x,
+                    y: paper_y,
+                },
+            ),
+        ),
+    ];
+    let mut violations = Vec::new();
+    for (idx, geometry) in project.plot_geometry.iter().enumerate() {
+        let lines = geometry
+            .transformed(&tx)
+            .geometry
+            .geometry()
+            .to_multi_line_strings();
+        // Tool offsets are in machine axes.
+        let tool_id = project
+            .pen_by_uuid(geometry.pen_uuid)
+            .unwrap_or(PenDetail::default())
+            .tool_id;
+        let (dx, dy) = machine.tool_offset(tool_id);
+        let shift = to_bed.transform_vector(&Vector2::new(-dx, -dy));
+        let carriage = lines.translate(shift.x, shift.y);
+        for (kind, rect) in &bounds {
+            let lines = match kind {
+                ViolationKind::MachineLimits => &carriage,
+                ViolationKind::Paper => &lines,
+            };
+            let mut segments = 0usize;
+            let mut first: Option<Coord<f64>> = None;
+            for line in &lines.0 {
+                let outside: Vec<bool> = line.0.iter().map(|p| !contains(rect, p)).collect();
+                if line.0.len() == 1 && outside[0] {
+                    segments += 1;
+                }
+                for (pair, points) in outside.windows(2).zip(line.0.windows(2)) {
+                    if pair[0] || pair[1] {
+                        segments += 1;
+                        first = first.or(Some(if pair[0] { points[0] } else { points[1] }));
+                    }
+                }
+                if first.is_none() && outside.first() == Some(&true) {
+                    first = Some(line.0[0]);
+                }
+            }
+            if let Some(first) = first {
+                let first = to_machine.transform_point(&Point2::new(first.x, first.y));
+                violations.push(Violation {
+                    geometry: idx,
+                    name: geometry.name.clone(),
+                    kind: kind.clone(),
+                    segments,
+                    first: (first.x, first.y),
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::machine::MachineConfig;
+    use crate::core::project::{BAPGeometry, GeometryKind, KeepdownStrategy};
+    use geo::{Geometry, LineString, coord};
+    use uuid::Uuid;
+
+    fn line_geometry(name: &str, points: Vec<Coord<f64>>) -> BAPGeometry {
+        BAPGeometry {
+            pen_uuid: Uuid::new_v4(),
+            name: name.to_string(),
+            geometry: GeometryKind::Stroke(Geometry::LineString(LineString::new(points))),
+            keepdown_strategy: KeepdownStrategy::None,
+            pinned: false,
+            marks: false,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        // A 235mm square machine, with portrait letter paper.
+        let mut project = Project::new();
+        project.set_machine(Some(MachineConfig::default()));
+        // Machine origin at (0, 300), so machine Y is 300 - project Y.
+        project.set_origin(&Some((0., 300.)));
+        project.plot_geometry.push(line_geometry(
+            "inside",
+            vec![coord! {x: 10., y: 290.}, coord! {x: 100., y: 200.}],
+        ));
+        // Inside the 235mm machine, but off the edge of letter paper.
+        project.plot_geometry.push(line_geometry(
+            "wide",
+            vec![coord! {x: 10., y: 290.}, coord! {x: 230., y: 290.}],
+        ));
+        // Off everything.
+        project.plot_geometry.push(line_geometry(
+            "gone",
+            vec![
+                coord! {x: 10., y: 290.},
+                coord! {x: 10., y: 0.},
+                coord! {x: 20., y: 0.},
+            ],
+        ));
+        let violations = validate(&project).expect("Failed to validate");
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[0].geometry, 1);
+        assert_eq!(violations[0].kind, ViolationKind::Paper);
+        assert_eq!(violations[0].first, (230., 10.));
+        assert_eq!(violations[1].geometry, 2);
+        assert_eq!(violations[1].kind, ViolationKind::MachineLimits);
+        assert_eq!(violations[1].segments, 2);
+        assert_eq!(violations[2].kind, ViolationKind::Paper);
+
+        // Offsetting the tool 20mm in X pushes the carriage off the left
+        // edge for the first line, but the ink still lands on the paper.
+        let mut machine = project.machine().unwrap();
+        machine.set_tool_offsets(
+            [(PenDetail::default().tool_id, (20., 0.))]
+                .into_iter()
+                .collect(),
+        );
+        project.set_machine(Some(machine));
+        let violations = validate(&project).expect("Failed to validate");
+        assert_eq!(violations.len(), 4);
+        assert_eq!(violations[0].geometry, 0);
+        assert_eq!(violations[0].kind, ViolationKind::MachineLimits);
+        assert_eq!(violations[0].first, (-10., 10.));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::BAPViewModel;
+use crate::core::estimate::format_duration;
 use eframe::egui;
 use egui::{Layout, ProgressBar};
 
@@ -20,6 +21,10 @@ pub fn bottom_panel(model: &mut BAPViewModel, ctx: &egui::Context) {
                             .desired_width(320.),
                     );
                 }
+                if let Some(estimate) = model.plot_estimate() {
+                    ui.label(format!("⏱{}", format_duration(estimate.total().seconds)))
+                        .on_hover_text(format!("{}", estimate));
+                }
                 if let Some(pos) = ctx.pointer_latest_pos() {
                     ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
                         let pos = model.frame_coords_to_mm(pos);
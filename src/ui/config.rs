@@ -1,6 +1,12 @@
-use crate::{BAPViewModel, core::commands::ViewCommand};
+use crate::{
+    BAPViewModel,
+    core::{
+        commands::ViewCommand,
+        config::{PathStrategy, SimplifyMethod},
+    },
+};
 use eframe::egui;
-use egui::{CollapsingHeader, Layout, ScrollArea};
+use egui::{CollapsingHeader, Layout, ScrollArea, Slider};
 
 pub(crate) fn config_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
     egui::Modal::new(egui::Id::new("Global Configuration"))
@@ -24,7 +30,107 @@ pub(crate) fn config_editor_window(model: &mut BAPViewModel, ctx: &egui::Context
                             "Enabling this option ensures that tool operations will \
                         be kept together by tool, reducing tool changes at the expense \
                         of losing the ability to interleave layers of colors.",
-                        )
+                        );
+                        ui.add_space(4.);
+                        ui.checkbox(
+                            &mut model.config_mut().post_options.pool_by_pen,
+                            "Optimize travel across geometries",
+                        );
+                        ui.label(
+                            "Pools all the consecutive geometry drawn with the same pen \
+                        and optimizes travel across the lot, instead of finishing each \
+                        geometry before starting the next. Pin a layer (⚓) to stop it \
+                        being pooled with the layers above it.",
+                        );
+                        ui.add_space(4.);
+                        let post_options = &mut model.config_mut().post_options;
+                        egui::ComboBox::from_label("Path optimization")
+                            .selected_text(format!("{}", post_options.path_strategy))
+                            .show_ui(ui, |ui| {
+                                for strategy in PathStrategy::all() {
+                                    let label = format!("{}", strategy);
+                                    ui.selectable_value(
+                                        &mut post_options.path_strategy,
+                                        strategy,
+                                        label,
+                                    );
+                                }
+                            });
+                        if post_options.path_strategy == PathStrategy::TwoOpt {
+                            ui.add(
+                                Slider::new(&mut post_options.two_opt_budget_ms, 100..=60000)
+                                    .logarithmic(true)
+                                    .text("2-opt time budget")
+                                    .custom_formatter(|n, _| format!("{:.1}s", n / 1000.)),
+                            );
+                        }
+                        ui.label(
+                            "Greedy is fast and fine for most work. Nearest neighbour flips \
+                        lines to start at whichever end is closer, and 2-opt keeps improving \
+                        on that until the time budget runs out. The post reports the pen-up \
+                        travel before and after so you can see if it was worth the wait.",
+                        );
+                        ui.add_space(4.);
+                        let post_options = &mut model.config_mut().post_options;
+                        let mut arc_tolerance = post_options.arc_tolerance.unwrap_or(0.0);
+                        ui.add(
+                            Slider::new(&mut arc_tolerance, 0.0f64..=0.5f64)
+                                .text(if arc_tolerance > 0.0 {
+                                    "Arc tolerance"
+                                } else {
+                                    "Arc fitting disabled"
+                                })
+                                .custom_formatter(|n, _| {
+                                    if n > 0.0 {
+                                        format!("{:1.3}mm", n)
+                                    } else {
+                                        format!("None")
+                                    }
+                                }),
+                        );
+                        post_options.arc_tolerance = if arc_tolerance > 0.0 {
+                            Some(arc_tolerance)
+                        } else {
+                            None
+                        };
+                        ui.label(
+                            "Replaces runs of tiny line moves along curves with G2/G3 arcs, \
+                        as long as every point stays within the tolerance. Needs the machine \
+                        to have arc_cw and arc_ccw post templates.",
+                        );
+                        ui.add_space(4.);
+                        let post_options = &mut model.config_mut().post_options;
+                        egui::ComboBox::from_label("Simplification")
+                            .selected_text(format!("{}", post_options.simplify))
+                            .show_ui(ui, |ui| {
+                                for method in SimplifyMethod::all() {
+                                    let label = format!("{}", method);
+                                    ui.selectable_value(&mut post_options.simplify, method, label);
+                                }
+                            });
+                        if post_options.simplify != SimplifyMethod::None {
+                            ui.add(
+                                Slider::new(&mut post_options.simplify_tolerance, 0.001..=1.0)
+                                    .logarithmic(true)
+                                    .text("Tolerance")
+                                    .custom_formatter(|n, _| format!("{:1.3}mm", n)),
+                            );
+                        }
+                        ui.label(
+                            "Drops vertices that wander less than the tolerance from the \
+                        simplified line. Pens can override the tolerance with a fraction of \
+                        their stroke width in the pen editor.",
+                        );
+                        ui.add_space(4.);
+                        ui.checkbox(
+                            &mut model.config_mut().post_options.refuse_invalid,
+                            "Refuse to post geometry off the paper or machine",
+                        );
+                        ui.label(
+                            "Every post checks the geometry against the machine limits and the \
+                        paper, and selects anything that strays outside. With this on, the \
+                        post stops there until it's fixed, instead of just warning.",
+                        );
                     });
             });
             ScrollArea::vertical().show(ui, |ui| {
@@ -255,6 +255,17 @@ pub(crate) fn floating_geo_layer_window(
                             if name_edit_resp.lost_focus() {
                                 model.set_inhibit_space_command(false);
                             }
+                            let mut pinned = model.geo_layers()[idx].pinned;
+                            if ui
+                                .toggle_value(&mut pinned, "⚓")
+                                .on_hover_text(
+                                    "Pin: never pool with the layers above when optimizing travel",
+                                )
+                                .changed()
+                            {
+                                model.geo_layers_mut()[idx].pinned = pinned;
+                                model.update_layer_pinned(idx, pinned);
+                            }
                             layer_drag_response
                         }); // ui.horizontal
 
@@ -1,7 +1,18 @@
 use egui::{Align2, Color32, FontId, Id, Layout, Rect, Slider, Stroke, TextEdit, Vec2, pos2, vec2};
 use indexmap::IndexMap;
 
-use crate::{core::commands::ViewCommand, view_model::BAPViewModel};
+use crate::{
+    core::{
+        commands::ViewCommand,
+        machine::{MachineConfig, MachineVariant, OriginCorner, PenZ, Streaming, ToolSlot},
+        template_lint::{TemplateLint, lint_templates},
+    },
+    view_model::BAPViewModel,
+};
+
+/// How long the templates have to sit unedited (in seconds) before they're
+/// linted again.
+const LINT_DELAY: f64 = 0.5;
 
 pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
     egui::Modal::new(Id::new("Machine Editor"))
@@ -26,6 +37,76 @@ pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
                 // This is the skim height section. It handles how high we lift
                 // the pen when doing rapids between lines.
                 let _configuration_response = ui.collapsing("Configuration", |ui|{
+                    // Dialect
+                    {
+                        let mut tmp_variant = model.machine_config_mut().variant();
+                        egui::ComboBox::from_label("Dialect")
+                            .selected_text(format!("{}", tmp_variant))
+                            .show_ui(ui, |ui| {
+                                for variant in MachineVariant::all() {
+                                    let label = format!("{}", variant);
+                                    ui.selectable_value(&mut tmp_variant, variant, label);
+                                }
+                            });
+                        model.machine_config_mut().set_variant(tmp_variant);
+                        ui.label("GRBL machines are posted through the templates below. HPGL machines (old Roland/HP pen plotters) \
+                            get native IN/SP/PU/PD/VS output instead, and are streamed without waiting for an 'ok'.");
+                        ui.add_space(4.);
+                    }
+                    // Home corner and axis directions
+                    {
+                        let mut tmp_axes = model.machine_config_mut().axes();
+                        egui::ComboBox::from_label("Home corner")
+                            .selected_text(format!("{}", tmp_axes.origin_corner))
+                            .show_ui(ui, |ui| {
+                                for corner in OriginCorner::all() {
+                                    let label = format!("{}", corner);
+                                    ui.selectable_value(&mut tmp_axes.origin_corner, corner, label);
+                                }
+                            });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut tmp_axes.invert_x, "Invert X");
+                            ui.checkbox(&mut tmp_axes.invert_y, "Invert Y");
+                            ui.checkbox(&mut tmp_axes.swap_xy, "Swap X/Y");
+                        });
+                        model.machine_config_mut().set_axes(tmp_axes);
+                        ui.label("Which corner of the bed the machine homes to, looking down on it. From home, X normally runs \
+                            across the bed and Y runs along it. Invert an axis if the machine counts down (negative) into the bed, \
+                            and swap them if machine X runs up the page. The limits overlay marks home and the +X/+Y directions.");
+                        ui.add_space(4.);
+                    }
+                    // Modal compression
+                    {
+                        let mut tmp_modal = model.machine_config_mut().modal();
+                        ui.checkbox(&mut tmp_modal, "Modal G-code");
+                        model.machine_config_mut().set_modal(tmp_modal);
+                        ui.label("Drops words that repeat the machine's modal state (motion mode, feedrate and unchanged axes), \
+                            so a run of G01 F1200 X.. Y.. lines become just the coordinates that changed. Cuts the line length and \
+                            serial bandwidth a lot on long jobs. Only applies to GRBL machines.");
+                        ui.add_space(4.);
+                    }
+                    // Streaming
+                    {
+                        let mut tmp_streaming = model.machine_config_mut().streaming();
+                        egui::ComboBox::from_label("Streaming")
+                            .selected_text(format!("{}", tmp_streaming))
+                            .show_ui(ui, |ui| {
+                                for streaming in Streaming::all() {
+                                    let label = format!("{}", streaming);
+                                    ui.selectable_value(&mut tmp_streaming, streaming, label);
+                                }
+                            });
+                        let mut tmp_rx_buffer = model.machine_config_mut().rx_buffer();
+                        ui.add_enabled(
+                            tmp_streaming == Streaming::CharacterCounting,
+                            egui::DragValue::new(&mut tmp_rx_buffer).range(16..=16384).suffix(" byte RX buffer"),
+                        );
+                        model.machine_config_mut().set_streaming(tmp_streaming);
+                        model.machine_config_mut().set_rx_buffer(tmp_rx_buffer);
+                        ui.label("Ok counting waits for each line to be acknowledged, which suits Marlin. Character counting \
+                            keeps GRBL's receive buffer (128 bytes on most boards) full, so the planner never runs dry on short lines.");
+                        ui.add_space(4.);
+                    }
                     {
                         let mut skim = model.machine_config_mut().skim().unwrap_or(0.0);
                         ui.add(
@@ -52,6 +133,41 @@ pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
                         ui.label("Skim defines the height above the media that the pen will rise to before high-speed travel moves between lines.");
                         ui.add_space(4.);
                     } //skim height
+                    // Z axis pen
+                    {
+                        let mut tmp_pen_z = model.machine_config_mut().pen_z();
+                        let mut use_z = tmp_pen_z.is_some();
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut use_z, "Z axis pen");
+                            if ui.button("Use Z axis templates").clicked() {
+                                model.machine_config_mut().set_post_template(&MachineConfig::z_axis_templates());
+                                use_z = true;
+                            }
+                        });
+                        if use_z {
+                            let pen_z = tmp_pen_z.get_or_insert_with(PenZ::default);
+                            ui.add(Slider::new(&mut pen_z.travel, -10.0..=50.0).text("Travel Z(mm)"));
+                            ui.add(Slider::new(&mut pen_z.skim, -10.0..=50.0).text("Skim Z(mm)"));
+                            ui.add(Slider::new(&mut pen_z.contact, -10.0..=10.0).text("Contact Z(mm)"));
+                            ui.horizontal(|ui| {
+                                let mut pressure = pen_z.pressure.is_some();
+                                let mut depth = pen_z.pressure.unwrap_or(0.5);
+                                ui.checkbox(&mut pressure, "Pressure from density");
+                                if pressure {
+                                    ui.add(Slider::new(&mut depth, 0.0..=5.0).text("Extra depth(mm)"));
+                                }
+                                pen_z.pressure = if pressure { Some(depth) } else { None };
+                            });
+                        } else {
+                            tmp_pen_z = None;
+                        }
+                        model.machine_config_mut().set_pen_z(tmp_pen_z);
+                        ui.label("For machines that lift the pen with a real Z axis instead of a servo. Templates get the height \
+                            each section should move the pen to as zmm: travel for the prelude, tool changes and epilog, skim between \
+                            lines, and contact for drawing. With pressure on, a pen's stroke density pushes it deeper than contact, \
+                            so soft pencils and pressure sensitive nibs draw darker.");
+                        ui.add_space(4.);
+                    }
                     // Keepdown
                     {
                         let mut tmp_keepdown=model.machine_config_mut().keepdown().unwrap_or(0.);
@@ -95,9 +211,71 @@ pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
                         ui.add_space(4.);
                     }
 
+                    // Motion, for the time estimate
+                    {
+                        let mut tmp_rapid = model.machine_config_mut().rapid_feedrate();
+                        ui.horizontal(|ui| {
+                            ui.add(Slider::new(&mut tmp_rapid, 100.0..=20000.0))
+                                .labelled_by(ui.label("Rapid(mm/min)").id);
+                        });
+                        model.machine_config_mut().set_rapid_feedrate(tmp_rapid);
+                        let (mut tmp_ax, mut tmp_ay) = model.machine_config_mut().acceleration();
+                        ui.horizontal(|ui| {
+                            ui.add(Slider::new(&mut tmp_ax, 10.0..=20000.0).logarithmic(true))
+                                .labelled_by(ui.label("Accel X(mm/s²)").id);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(Slider::new(&mut tmp_ay, 10.0..=20000.0).logarithmic(true))
+                                .labelled_by(ui.label("Accel Y(mm/s²)").id);
+                        });
+                        model.machine_config_mut().set_acceleration((tmp_ax, tmp_ay));
+                        ui.label("The rapid rate and per-axis acceleration aren't sent to the machine. They're only used to \
+                            estimate how long a plot will take, so copy them from your firmware settings ($110/$120 on GRBL).");
+                        ui.add_space(4.);
+                    }
+
                 });
 
                 let _templates_response = ui.collapsing("Post Templates", |ui|{
+                    // Linting posts a test path, so only redo it once the machine has
+                    // stopped changing for a moment, rather than on every keystroke.
+                    let lint_id = Id::new("machine-template-lint");
+                    let edited_id = Id::new("machine-template-lint-edited");
+                    let machine = model.machine_config();
+                    let now = ui.input(|input| input.time);
+                    let linted = ui.data(|data| data.get_temp::<(MachineConfig, TemplateLint)>(lint_id));
+                    let lint = match linted {
+                        Some((linted, lint)) if linted == machine => lint,
+                        linted => {
+                            let edited = match ui.data(|data| data.get_temp::<(MachineConfig, f64)>(edited_id)) {
+                                Some((edited, at)) if edited == machine => at,
+                                _ => {
+                                    ui.data_mut(|data| data.insert_temp(edited_id, (machine.clone(), now)));
+                                    now
+                                }
+                            };
+                            match linted {
+                                Some((_, lint)) if now - edited < LINT_DELAY => {
+                                    ui.ctx().request_repaint_after_secs((LINT_DELAY - (now - edited)) as f32);
+                                    lint
+                                }
+                                _ => {
+                                    let lint = lint_templates(&machine);
+                                    ui.data_mut(|data| data.insert_temp(lint_id, (machine.clone(), lint.clone())));
+                                    lint
+                                }
+                            }
+                        }
+                    };
+                    let error_color = ui.visuals().error_fg_color;
+                    if lint.is_clean() {
+                        ui.label("✔ All templates render.");
+                    } else {
+                        ui.colored_label(error_color, format!("⚠ {} problem(s) with the templates.", lint.issues.len()));
+                    }
+                    for (name, issue) in lint.issues.iter().filter(|(name, _)| !machine.has_post_section(name)) {
+                        ui.colored_label(error_color, format!("{}: {}", name, issue));
+                    }
                     let mut templates: IndexMap<String, String> = IndexMap::from_iter(
                         model.machine_config_mut().get_post_template()
                             .iter()
@@ -114,9 +292,33 @@ pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
                                 let te = TextEdit::multiline(tpl).desired_width(550.0).desired_rows(10);
                                 if ui.add(te).changed(){update=true}
                             });
+                        for issue in lint.issues_for(name) {
+                            ui.colored_label(error_color, issue);
+                        }
 
                     }
 
+                    ui.add_space(8.);
+                    ui.label("Test path (a 20mm square and its diagonal), as posted:");
+                    match &lint.sample {
+                        Ok(program) => {
+                            egui::ScrollArea::vertical()
+                                .id_salt("template-lint-sample")
+                                .auto_shrink(true)
+                                .max_height(150.)
+                                .show(ui, |ui| {
+                                    let mut sample = program.join("\n");
+                                    ui.add(TextEdit::multiline(&mut sample)
+                                        .code_editor()
+                                        .interactive(false)
+                                        .desired_width(550.0));
+                                });
+                        }
+                        Err(err) => {
+                            ui.colored_label(error_color, err);
+                        }
+                    }
+
                     if update{
                         model.machine_config_mut().set_post_template(&templates.iter().map(|(k,v)|(k.clone(), v.clone())).collect());
 
@@ -125,6 +327,68 @@ pub fn machine_editor_window(model: &mut BAPViewModel, ctx: &egui::Context) {
                 });
                 // ui.add_space(8.);
                 // ui.separator();
+                let _offsets_response = ui.collapsing("Tool Offsets", |ui|{
+                    let mut tool_offsets = model.machine_config_mut().tool_offsets();
+                    let mut tools: Vec<(usize, String)> = model.pen_crib()
+                        .iter()
+                        .map(|pen| (pen.tool_id, pen.name.clone()))
+                        .collect();
+                    tools.sort_by_key(|(tool_id, _)| *tool_id);
+                    tools.dedup_by_key(|(tool_id, _)| *tool_id);
+                    for (tool_id, name) in tools {
+                        let (mut dx, mut dy) = tool_offsets.get(&tool_id).copied().unwrap_or((0., 0.));
+                        ui.horizontal(|ui| {
+                            ui.label(format!("T{} ({})", tool_id, name));
+                            ui.add(egui::DragValue::new(&mut dx).speed(0.05).prefix("X:").suffix("mm"));
+                            ui.add(egui::DragValue::new(&mut dy).speed(0.05).prefix("Y:").suffix("mm"));
+                        });
+                        if dx == 0. && dy == 0. {
+                            tool_offsets.remove(&tool_id);
+                        } else {
+                            tool_offsets.insert(tool_id, (dx, dy));
+                        }
+                    }
+                    model.machine_config_mut().set_tool_offsets(tool_offsets);
+                    ui.label("Where each tool's tip sits relative to the carriage's reference point, in machine axes. \
+                        Everything drawn with a tool is shifted back by its offset, so colors line up on dual-pen and \
+                        carousel machines.");
+                });
+                let _changer_response = ui.collapsing("Tool Changer", |ui|{
+                    let mut tool_slots = model.machine_config_mut().tool_slots();
+                    let mut remove: Option<usize> = None;
+                    for (idx, slot) in tool_slots.iter_mut().enumerate() {
+                        ui.add_space(4.);
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut slot.tool_id).prefix("T"));
+                            ui.label("Slot");
+                            ui.add(egui::DragValue::new(&mut slot.position.0).speed(0.1).prefix("X:"));
+                            ui.add(egui::DragValue::new(&mut slot.position.1).speed(0.1).prefix("Y:"));
+                            ui.label("Approach");
+                            ui.add(egui::DragValue::new(&mut slot.approach.0).speed(0.1).prefix("X:"));
+                            ui.add(egui::DragValue::new(&mut slot.approach.1).speed(0.1).prefix("Y:"));
+                            if ui.button("🗑").clicked() {
+                                remove = Some(idx);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Grab");
+                            ui.add(TextEdit::multiline(&mut slot.grab).id_salt(format!("slot-grab-{}", idx)).desired_width(250.).desired_rows(2));
+                            ui.label("Release");
+                            ui.add(TextEdit::multiline(&mut slot.release).id_salt(format!("slot-release-{}", idx)).desired_width(250.).desired_rows(2));
+                        });
+                    }
+                    if let Some(idx) = remove {
+                        tool_slots.remove(idx);
+                    }
+                    if ui.button("Add slot").clicked() {
+                        let tool_id = tool_slots.iter().map(|slot| slot.tool_id + 1).max().unwrap_or(1);
+                        tool_slots.push(ToolSlot { tool_id, ..ToolSlot::default() });
+                    }
+                    model.machine_config_mut().set_tool_slots(tool_slots);
+                    ui.label("Pens with a slot are swapped automatically: the carriage travels to the approach point, feeds \
+                        into the slot, runs the release or grab sequence (a template, like the post templates) and backs out. \
+                        Pens without a slot still go through the toolchange template, which usually pauses for you.");
+                });
                 let _limits_response = ui.collapsing("Machine Limits", |ui|{
                     let (painter_resp, painter) = ui.allocate_painter(vec2(600., 320.), egui::Sense::all());
                     let limits = model.machine_config().limits();
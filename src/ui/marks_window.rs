@@ -0,0 +1,79 @@
+use crate::core::commands::MarksTarget;
+use crate::view_model::{BAPViewModel, CommandContext};
+use eframe::egui;
+use egui::{ComboBox, Id, Layout, Slider};
+
+pub(crate) fn marks_window(model: &mut BAPViewModel, ctx: &egui::Context) {
+    egui::Modal::new(Id::new("CropMarks")).show(ctx, |ui| {
+        ui.set_width(350.);
+        ui.heading("Crop and registration marks");
+
+        if let CommandContext::Marks(config) = model.command_context() {
+            let mut config = config.clone();
+            ComboBox::from_label("Around")
+                .selected_text(format!("{}", config.target))
+                .show_ui(ui, |ui| {
+                    for target in MarksTarget::all() {
+                        let label = format!("{}", target);
+                        ui.selectable_value(&mut config.target, target, label);
+                    }
+                });
+            let pens = model.pen_crib();
+            let selected = config
+                .pen_uuid
+                .and_then(|uuid| pens.iter().find(|pen| pen.identity == uuid))
+                .or(pens.first())
+                .map(|pen| pen.name.clone())
+                .unwrap_or_default();
+            ComboBox::from_label("Pen")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for pen in &pens {
+                        ui.selectable_value(&mut config.pen_uuid, Some(pen.identity), &pen.name);
+                    }
+                });
+            ui.add(
+                Slider::new(&mut config.bleed, 0.0..=20.0)
+                    .custom_formatter(|val, _range| format!("{:0.1}mm", val))
+                    .text("Bleed"),
+            );
+            ui.label("Outside the extents, or in from the paper's edge.");
+            ui.checkbox(&mut config.bleed_box, "Bleed box");
+            ui.checkbox(&mut config.crop_marks, "Corner crop marks");
+            ui.add(
+                Slider::new(&mut config.crop_length, 1.0..=20.0)
+                    .custom_formatter(|val, _range| format!("{:0.1}mm", val))
+                    .text("Length"),
+            );
+            ui.add(
+                Slider::new(&mut config.crop_offset, 0.0..=10.0)
+                    .custom_formatter(|val, _range| format!("{:0.1}mm", val))
+                    .text("Offset"),
+            );
+            ui.checkbox(&mut config.crosshairs, "Center cross-hairs");
+            ui.add(
+                Slider::new(&mut config.crosshair_size, 1.0..=20.0)
+                    .custom_formatter(|val, _range| format!("{:0.1}mm", val))
+                    .text("Size"),
+            );
+            ui.label("Marks are redrawn whenever the artwork is matted.");
+            model.set_command_context(CommandContext::Marks(config.clone()));
+            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Ok").clicked() {
+                    model.set_marks(Some(config.clone()));
+                    model.cancel_command_context(false);
+                }
+                if ui
+                    .add_enabled(model.marks().is_some(), egui::Button::new("Remove"))
+                    .clicked()
+                {
+                    model.set_marks(None);
+                    model.cancel_command_context(false);
+                }
+                if ui.button("Cancel").clicked() {
+                    model.cancel_command_context(true);
+                }
+            });
+        }
+    });
+}
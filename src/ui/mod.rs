@@ -1,3 +1,4 @@
+use crate::core::resume::ResumeFrom;
 use crate::ui::machine::machine_editor_window;
 // use crate::ui::bottom_panel::bottom_panel;
 use crate::ui::menu::main_menu;
@@ -9,8 +10,9 @@ use crate::view_model::command_context::SpaceCommandStatus;
 use crate::view_model::{BAPViewModel, CommandContext};
 use eframe::egui;
 use egui::Direction::BottomUp;
-use egui::{Align2, Color32, FontId, Frame, Key, Rect, Stroke, StrokeKind, pos2, vec2};
+use egui::{Align2, Color32, FontId, Frame, Key, Pos2, Rect, Stroke, StrokeKind, pos2, vec2};
 use egui_toast::Toasts;
+use nalgebra::Point2;
 
 pub(crate) mod arrange_mat;
 pub(crate) mod bottom_panel;
@@ -19,6 +21,7 @@ pub(crate) mod editor;
 pub(crate) mod geo_layers;
 pub(crate) mod hatch_tool_window;
 pub(crate) mod machine;
+pub(crate) mod marks_window;
 pub(crate) mod menu;
 pub(crate) mod paper_chooser;
 pub(crate) mod pen_crib;
@@ -29,10 +32,37 @@ pub(crate) mod scale_window;
 pub(crate) mod scene_toggle;
 pub(crate) mod space_command_palette;
 pub(crate) mod themes;
+pub(crate) mod tile_window;
 pub(crate) mod tool_button;
 pub(crate) mod tool_window;
 use tool_window::floating_tool_window;
 
+/// Marks the machine's home on the bed whose bottom left corner is at
+/// origin, with short arrows showing which way +X and +Y run.
+fn paint_machine_home(model: &BAPViewModel, painter: &egui::Painter, origin: Pos2) {
+    let Some(machine_to_bed) = model.machine_config().bed_to_machine().try_inverse() else {
+        return;
+    };
+    let machine_to_screen = |x: f64, y: f64| {
+        let bed = machine_to_bed.transform_point(&Point2::new(x, y));
+        model.mm_to_frame_coords(pos2(origin.x + bed.x as f32, origin.y - bed.y as f32))
+    };
+    let home = machine_to_screen(0., 0.);
+    let stroke = Stroke::new(1.5, Color32::YELLOW);
+    painter.circle(home, 5., Color32::TRANSPARENT, stroke);
+    for (label, tip) in [("X", (15., 0.)), ("Y", (0., 15.))] {
+        let tip = machine_to_screen(tip.0, tip.1);
+        painter.arrow(home, tip - home, stroke);
+        painter.text(
+            tip,
+            Align2::CENTER_CENTER,
+            label,
+            FontId::default(),
+            Color32::YELLOW,
+        );
+    }
+}
+
 pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &mut eframe::Frame) {
     // Looks better on 4k montior
     ctx.set_pixels_per_point(model.ppp());
@@ -102,6 +132,8 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
         CommandContext::HatchGeometry(_hatch_config) => {
             hatch_tool_window::floating_hatch_tool_window(model, ctx, wtop, &mut toasts);
         }
+        CommandContext::Tile(_config) => tile_window::tile_window(model, ctx),
+        CommandContext::Marks(_config) => marks_window::marks_window(model, ctx),
 
         _ => (),
     }
@@ -113,7 +145,7 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
         // let painter = ui.painter();
         let (painter_resp, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::all());
         let painter_resp = painter_resp.on_hover_cursor(match model.command_context() {
-            CommandContext::Origin => egui::CursorIcon::Crosshair,
+            CommandContext::Origin | CommandContext::PickResume => egui::CursorIcon::Crosshair,
             _ => egui::CursorIcon::Default,
         });
 
@@ -302,10 +334,10 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
                 let machine_rect = model.mm_rect_to_screen_rect(Rect::from_min_max(
                     pos2(
                         tmp_origin.x,
-                        tmp_origin.y - model.machine_config().limits().1 as f32,
+                        tmp_origin.y - model.machine_config().bed_size().1 as f32,
                     ),
                     pos2(
-                        tmp_origin.x + model.machine_config().limits().0 as f32,
+                        tmp_origin.x + model.machine_config().bed_size().0 as f32,
                         tmp_origin.y,
                     ),
                 ));
@@ -317,6 +349,7 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
                         Stroke::new(1., Color32::YELLOW),
                         StrokeKind::Outside,
                     );
+                    paint_machine_home(model, &painter, tmp_origin);
                 }
             };
         }
@@ -422,10 +455,10 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
         let machine_rect = model.mm_rect_to_screen_rect(Rect::from_min_max(
             pos2(
                 model.origin().x,
-                model.origin().y - model.machine_config().limits().1 as f32,
+                model.origin().y - model.machine_config().bed_size().1 as f32,
             ),
             pos2(
-                model.origin().x + model.machine_config().limits().0 as f32,
+                model.origin().x + model.machine_config().bed_size().0 as f32,
                 model.origin().y,
             ),
         ));
@@ -437,6 +470,7 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
                 Stroke::new(1., Color32::YELLOW),
                 StrokeKind::Outside,
             );
+            paint_machine_home(model, &painter, model.origin());
         }
         if model.show_extents() {
             if let Some(extents) = model.source_image_extents() {
@@ -554,6 +588,13 @@ pub(crate) fn update_ui(model: &mut BAPViewModel, ctx: &egui::Context, _frame: &
                         model.cancel_command_context(false);
                     }
                 }
+                CommandContext::PickResume => {
+                    if let Some(pos) = ctx.pointer_hover_pos() {
+                        let pos = model.frame_coords_to_mm(pos);
+                        model.plot_resume(ResumeFrom::Point(pos.x as f64, pos.y as f64));
+                        model.cancel_command_context(false);
+                    }
+                }
                 CommandContext::Translate(opt_pos) => {
                     if let Some(hover_pos) = ctx.pointer_hover_pos() {
                         if opt_pos.is_none() {
@@ -4,7 +4,7 @@ use csscolorparser::Color;
 use egui::{Color32, Id, Layout, Rect, Slider, Stroke, StrokeKind, epaint::PathStroke, pos2, vec2};
 
 use crate::{
-    core::project::PenDetail,
+    core::project::{DrawDirection, PenDetail, PenPostSettings},
     view_model::{BAPViewModel, CommandContext},
 };
 
@@ -18,7 +18,7 @@ pub fn pen_editor_window(model: &mut BAPViewModel, ctx: &egui::Context, pen_idx:
                 pen_idx,
                 crib.get(pen_idx).unwrap_or(&PenDetail::default()).name
             ));
-            let (painter_resp, painter) = ui.allocate_painter(vec2(390., 460.), egui::Sense::all());
+            let (painter_resp, painter) = ui.allocate_painter(vec2(390., 550.), egui::Sense::all());
             let prect = painter_resp.rect;
             let ofs = (prect.min.clone() + vec2(10., 10.)).to_vec2();
             let pen_crib_len = crib.len();
@@ -245,14 +245,116 @@ pub fn pen_editor_window(model: &mut BAPViewModel, ctx: &egui::Context, pen_idx:
                 PathStroke::new(1., ui.visuals().text_color()),
             );
 
+            // Post settings live in the project rather than on the pen, so we
+            // edit a scratch copy and only apply it when Ok is clicked.
+            let pen_uuid = model.pen_crib()[pen_idx].identity;
+            let stroke_width = model.pen_crib()[pen_idx].stroke_width;
+            let post_edit_id = Id::new(format!("pen-post-edit-{}", pen_uuid));
+            let mut post_settings = ui
+                .data_mut(|data| data.get_temp::<PenPostSettings>(post_edit_id))
+                .unwrap_or_else(|| model.pen_post_settings(pen_uuid));
+
+            #[allow(deprecated)]
+            ui.allocate_ui_at_rect(
+                Rect::from_min_max(pos2(0., 400.) + ofs, pos2(390.0, 430.0) + ofs),
+                |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Simplify");
+                        let (mut enabled, mut fraction) =
+                            match post_settings.simplify_width_fraction {
+                                Some(fraction) => (true, fraction),
+                                None => (false, 0.25),
+                            };
+                        ui.checkbox(&mut enabled, "Custom");
+                        if enabled {
+                            ui.add(Slider::new(&mut fraction, 0.05..=1.0).custom_formatter(
+                                |n, _| format!("{:1.2}x width ({:1.3}mm)", n, n * stroke_width),
+                            ));
+                        } else {
+                            ui.label(" - post default -");
+                        }
+                        post_settings.simplify_width_fraction =
+                            if enabled { Some(fraction) } else { None };
+                    })
+                },
+            );
+
+            #[allow(deprecated)]
+            ui.allocate_ui_at_rect(
+                Rect::from_min_max(pos2(0., 430.) + ofs, pos2(390.0, 460.0) + ofs),
+                |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Passes");
+                        ui.add(Slider::new(&mut post_settings.passes, 1..=5));
+                        if post_settings.passes > 1 {
+                            ui.add(
+                                Slider::new(&mut post_settings.pass_offset, 0.0..=1.0)
+                                    .custom_formatter(|n, _| format!("{:1.2}mm offset", n)),
+                            );
+                        }
+                    })
+                },
+            );
+
+            #[allow(deprecated)]
+            ui.allocate_ui_at_rect(
+                Rect::from_min_max(pos2(0., 460.) + ofs, pos2(390.0, 490.0) + ofs),
+                |ui| {
+                    ui.add_enabled_ui(post_settings.passes > 1, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(
+                                &mut post_settings.reverse_passes,
+                                "Reverse alternate passes",
+                            )
+                            .on_hover_text(
+                                "Draws every other pass backwards, so the pen can stay down",
+                            );
+                            ui.checkbox(
+                                &mut post_settings.lift_between_passes,
+                                "Lift between passes",
+                            );
+                        })
+                    });
+                },
+            );
+
+            #[allow(deprecated)]
+            ui.allocate_ui_at_rect(
+                Rect::from_min_max(pos2(0., 490.) + ofs, pos2(390.0, 520.0) + ofs),
+                |ui| {
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::new(format!("pen-direction-{}", pen_uuid), "Direction")
+                            .selected_text(format!("{}", post_settings.direction))
+                            .show_ui(ui, |ui| {
+                                for direction in DrawDirection::all() {
+                                    let label = format!("{}", direction);
+                                    ui.selectable_value(
+                                        &mut post_settings.direction,
+                                        direction,
+                                        label,
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Brush and calligraphy pens only draw open lines one way. \
+                                Lines are still reordered, just never flipped.",
+                            );
+                    })
+                },
+            );
+            ui.data_mut(|data| data.insert_temp(post_edit_id, post_settings.clone()));
+
             #[allow(deprecated)]
             let _pen_density_slider_response = ui.allocate_ui_at_rect(
-                Rect::from_min_max(pos2(35., 430.) + ofs, pos2(390.0, 460.0) + ofs),
+                Rect::from_min_max(pos2(35., 520.) + ofs, pos2(390.0, 550.0) + ofs),
                 |ui| {
                     ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
                         if ui.button("Ok").clicked() {
                             // model.pen_crib_open = false
                             model.update_pen_details();
+                            model.update_pen_post_settings(pen_uuid, post_settings.clone());
+                            ui.data_mut(|data| data.remove::<PenPostSettings>(post_edit_id));
                             model.set_command_context(crate::view_model::CommandContext::PenCrib)
                         }
                         if ui.button("Cancel").clicked() {
@@ -263,6 +365,7 @@ pub fn pen_editor_window(model: &mut BAPViewModel, ctx: &egui::Context, pen_idx:
                                 let crib = model.pen_crib_mut();
                                 crib[*idx] = pen.clone()
                             }
+                            ui.data_mut(|data| data.remove::<PenPostSettings>(post_edit_id));
                             model.set_command_context(crate::view_model::CommandContext::PenCrib)
                         }
                     });
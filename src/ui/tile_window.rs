@@ -0,0 +1,77 @@
+use crate::core::commands::TileTarget;
+use crate::core::tile::tile_grid;
+use crate::view_model::{BAPViewModel, CommandContext};
+use eframe::egui;
+use egui::{ComboBox, Id, Layout, Slider};
+use geo::{Rect, coord};
+
+pub(crate) fn tile_window(model: &mut BAPViewModel, ctx: &egui::Context) {
+    egui::Modal::new(Id::new("ExportTiles")).show(ctx, |ui| {
+        ui.set_width(350.);
+        ui.heading("Export tiles");
+
+        if let CommandContext::Tile(config) = model.command_context() {
+            let mut config = config.clone();
+            ComboBox::from_label("Tile size")
+                .selected_text(format!("{}", config.target))
+                .show_ui(ui, |ui| {
+                    for target in TileTarget::all() {
+                        let label = format!("{}", target);
+                        ui.selectable_value(&mut config.target, target, label);
+                    }
+                });
+            ui.label("Each tile is the size of the machine's bed, or of the paper.");
+            ui.add(
+                Slider::new(&mut config.overlap, 0.0..=50.0)
+                    .custom_formatter(|val, _range| format!("{:0.1}mm", val))
+                    .text("Overlap"),
+            );
+            ui.label("How far neighbouring tiles overlap, so they can be lined up.");
+            ui.checkbox(&mut config.registration_marks, "Registration marks");
+            ui.label("Crosses in the overlap, to line the sheets up by.");
+
+            let size = match config.target {
+                TileTarget::Machine => model.machine_config().bed_size(),
+                TileTarget::Paper => model
+                    .paper_size()
+                    .dimensions_oriented(&model.paper_orientation()),
+            };
+            let tiles = model.source_image_extents().map(|extents| {
+                let extents = Rect::new(
+                    coord! {x: extents.min.x as f64, y: extents.min.y as f64},
+                    coord! {x: extents.max.x as f64, y: extents.max.y as f64},
+                );
+                tile_grid(&extents, size, config.overlap)
+            });
+            let can_export = match &tiles {
+                Some(Ok(tiles)) => {
+                    ui.label(format!(
+                        "Up to {} tile(s) of {:.0}x{:.0}mm",
+                        tiles.len(),
+                        size.0,
+                        size.1
+                    ));
+                    true
+                }
+                Some(Err(err)) => {
+                    ui.label(format!("{}", err));
+                    false
+                }
+                None => false,
+            };
+            model.set_command_context(CommandContext::Tile(config.clone()));
+            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui
+                    .add_enabled(can_export, egui::Button::new("Export…"))
+                    .clicked()
+                {
+                    model.export_tiles_with_dialog(config.clone());
+                    model.cancel_command_context(false);
+                }
+                if ui.button("Cancel").clicked() {
+                    model.cancel_command_context(true);
+                }
+            });
+        }
+    });
+}
@@ -3,6 +3,7 @@ use crate::core::commands::MatTarget;
 use crate::core::config::DockPosition;
 use crate::core::config::RulerOrigin;
 use crate::core::project::Orientation;
+use crate::core::resume::ResumeFrom;
 use crate::core::sender::PlotterState;
 use crate::ui::tool_button::toggle_button;
 use crate::view_model::{BAPDisplayMode, BAPViewModel, CommandContext};
@@ -594,6 +595,24 @@ pub(crate) fn floating_tool_window(
                                     }
                                 };
                             }
+                            ui.separator();
+                            // Network plotters can't be scanned for, so they get typed in.
+                            let uri_id = ui.id().with("network-plotter");
+                            let mut uri = ui
+                                .data_mut(|data| data.get_temp::<String>(uri_id))
+                                .unwrap_or("tcp://".to_string());
+                            let uri_resp = ui.add(
+                                TextEdit::singleline(&mut uri)
+                                    .hint_text("tcp://host:port or telnet://host"),
+                            );
+                            ui.data_mut(|data| data.insert_temp(uri_id, uri.clone()));
+                            if uri_resp.lost_focus()
+                                && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                                && model.plotter_state() == PlotterState::Disconnected
+                            {
+                                tmp_port = uri.trim().to_string();
+                                model.set_current_port(tmp_port.clone());
+                            }
                         });
                     if cb_resp.response.changed() {
                         //println!("Got a change on serial selector.");
@@ -801,6 +820,73 @@ pub(crate) fn floating_tool_window(
                                         model.gcode().clone(),
                                     )));
                                 }
+                                let can_resume = model.gcode().len() > 0
+                                    && match model.plotter_state() {
+                                        PlotterState::Ready => true,
+                                        PlotterState::Paused(_, _, _) => true,
+                                        _ => false,
+                                    };
+                                if tool_button(
+                                    ui,
+                                    egui::include_image!("../../resources/images/play_circle.png"),
+                                    Some(
+                                        "Resume plotting from a point picked on the plot preview"
+                                            .into(),
+                                    ),
+                                    can_resume,
+                                )
+                                .clicked()
+                                {
+                                    model.set_command_context(CommandContext::PickResume);
+                                }
+                                let picked_geometry = model
+                                    .picked()
+                                    .filter(|picked| picked.len() == 1)
+                                    .map(|picked| picked[0]);
+                                if tool_button(
+                                    ui,
+                                    egui::include_image!("../../resources/images/layers.png"),
+                                    Some(
+                                        "Resume plotting from the start of the selected geometry"
+                                            .into(),
+                                    ),
+                                    can_resume && picked_geometry.is_some(),
+                                )
+                                .clicked()
+                                {
+                                    if let Some(geometry) = picked_geometry {
+                                        model.plot_resume(ResumeFrom::Geometry(geometry));
+                                    }
+                                }
+                                // Program lines are numbered from 1 here, and start
+                                // out at wherever the plot was paused.
+                                let line_id = ui.id().with("resume-line");
+                                let mut line = ui
+                                    .data_mut(|data| data.get_temp::<usize>(line_id))
+                                    .unwrap_or(match model.plotter_state() {
+                                        PlotterState::Paused(line, _, _) => (line as usize).max(1),
+                                        _ => 1,
+                                    });
+                                ui.add_enabled(
+                                    can_resume,
+                                    egui::DragValue::new(&mut line)
+                                        .range(1..=model.gcode().len().max(1))
+                                        .prefix("Line "),
+                                );
+                                ui.data_mut(|data| data.insert_temp(line_id, line));
+                                if tool_button(
+                                    ui,
+                                    egui::include_image!("../../resources/images/gcode.png"),
+                                    Some(
+                                        "Resume plotting from the line drawn at this program line"
+                                            .into(),
+                                    ),
+                                    can_resume,
+                                )
+                                .clicked()
+                                {
+                                    model.plot_resume(ResumeFrom::Line(line - 1));
+                                }
                             });
                     });
                 ui.add_space(8.);
@@ -863,6 +949,16 @@ pub(crate) fn floating_tool_window(
                         };
                     };
                 });
+                if let Some(status) = model.machine_status() {
+                    let mut text = format!("{}", status.state);
+                    if let Some((x, y, _z)) = status.position {
+                        text += &format!(" X{:.1} Y{:.1}", x, y);
+                    }
+                    if let Some(feed) = status.feed {
+                        text += &format!(" F{:.0}", feed);
+                    }
+                    ui.small(text);
+                }
             };
         });
         if model.toolbar_position() == DockPosition::Left
@@ -12,7 +12,10 @@ use std::{
 };
 
 use crate::{
-    core::{commands::HatchConfig, commands::MatTarget, config::AppConfig, machine::MachineConfig},
+    core::{
+        commands::HatchConfig, commands::MarksConfig, commands::MatTarget, commands::TileConfig,
+        config::AppConfig, machine::MachineConfig,
+    },
     view_model::BAPViewModel,
 };
 // use crate::view_model::project_ops::project_ops;
@@ -38,6 +41,9 @@ pub enum CommandContext {
     Configure(Option<AppConfig>),
     MatToTarget(MatTarget),
     HatchGeometry(HatchConfig),
+    PickResume, // Click the plot preview to resume plotting from there.
+    Tile(TileConfig),
+    Marks(MarksConfig),
     None,
 }
 
@@ -88,6 +94,9 @@ impl Display for CommandContext {
             CommandContext::Configure(_) => write!(f, "Configuration"),
             CommandContext::MatToTarget(mat_target) => write!(f, "Arrange matted: {}", mat_target),
             CommandContext::HatchGeometry(hatch_config) => todo!(),
+            CommandContext::PickResume => write!(f, "Pick resume point"),
+            CommandContext::Tile(_) => write!(f, "Export tiles"),
+            CommandContext::Marks(_) => write!(f, "Crop marks"),
         }
     }
 }
@@ -224,6 +233,9 @@ impl BAPViewModel {
                 CommandContext::None
             }
             CommandContext::MatToTarget(_mat_target) => CommandContext::None,
+            CommandContext::PickResume => CommandContext::None,
+            CommandContext::Tile(_) => CommandContext::None,
+            CommandContext::Marks(_) => CommandContext::None,
             CommandContext::HatchGeometry(hatch_config) => todo!(),
         };
     }
@@ -248,6 +260,9 @@ impl BAPViewModel {
             CommandContext::EditGcode(_) => ctx,
             CommandContext::Configure(_app_config) => ctx,
             CommandContext::MatToTarget(_mat_target) => ctx,
+            CommandContext::PickResume => ctx,
+            CommandContext::Tile(_) => ctx,
+            CommandContext::Marks(_) => ctx,
             CommandContext::HatchGeometry(hatch_config) => todo!(),
         };
     }
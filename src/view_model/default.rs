@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use aoer_plotty_rs::plotter::pen::PenDetail;
 use egui::Modifiers;
@@ -58,6 +58,7 @@ impl Default for BAPViewModel {
             join_handle: None,
             move_increment: 5.,
             plotter_state: PlotterState::Disconnected,
+            machine_status: None,
             queued_toasts: VecDeque::new(),
             pen_crib: vec![
                 Default::default(),
@@ -80,6 +81,8 @@ impl Default for BAPViewModel {
                     ..Default::default()
                 },
             ],
+            pen_post: HashMap::new(),
+            marks: None,
             undo_available: false,
             file_path: None,
             ruler_origin: RulerOrigin::Source,
@@ -92,6 +95,7 @@ impl Default for BAPViewModel {
             ),
             modifiers: Modifiers::NONE,
             gcode: "".to_string(),
+            plot_estimate: None,
             geo_layers: Vec::new(),
             misc_textures: None,
         }
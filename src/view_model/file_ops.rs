@@ -1,4 +1,5 @@
-use crate::core::commands::ViewCommand;
+use crate::core::commands::{TileConfig, ViewCommand};
+use crate::core::machine::MachineVariant;
 
 use super::{BAPViewModel, FileDialog, FileSelector};
 
@@ -84,6 +85,43 @@ impl BAPViewModel {
         });
     }
 
+    pub fn export_gcode_with_dialog(&mut self, per_pen: bool) {
+        let (tx, rx) = mpsc::channel::<FileSelector>();
+        self.file_selector = Some(rx);
+        let is_hpgl = self.machine_config.variant() == MachineVariant::HPGL;
+        spawn(move || {
+            let dialog = if is_hpgl {
+                FileDialog::new().add_filter("hpgl", &["hpgl", "plt"])
+            } else {
+                FileDialog::new().add_filter("gcode", &["gcode", "nc", "gc"])
+            };
+            let file = dialog.set_directory("").save_file();
+            if let Some(path) = file {
+                tx.send(FileSelector::ExportGCode(path.into(), per_pen))
+                    .expect("Failed to export G-code");
+            }
+        });
+    }
+
+    /// Asks where to put the tiles, which get numbered files next to it.
+    pub fn export_tiles_with_dialog(&mut self, config: TileConfig) {
+        let (tx, rx) = mpsc::channel::<FileSelector>();
+        self.file_selector = Some(rx);
+        let is_hpgl = self.machine_config.variant() == MachineVariant::HPGL;
+        spawn(move || {
+            let dialog = if is_hpgl {
+                FileDialog::new().add_filter("hpgl", &["hpgl", "plt"])
+            } else {
+                FileDialog::new().add_filter("gcode", &["gcode", "nc", "gc"])
+            };
+            let file = dialog.set_directory("").save_file();
+            if let Some(path) = file {
+                tx.send(FileSelector::ExportTiles(path.into(), config))
+                    .expect("Failed to export tiles");
+            }
+        });
+    }
+
     pub fn open_project_with_dialog(&mut self) {
         let (tx, rx) = mpsc::channel::<FileSelector>();
         self.file_selector = Some(rx);
@@ -122,6 +160,12 @@ impl BAPViewModel {
                         FileSelector::LoadMachineFrom(path_buf) => {
                             self.yolo_view_command(ViewCommand::LoadMachineConfig(path_buf))
                         }
+                        FileSelector::ExportGCode(path, per_pen) => {
+                            self.yolo_view_command(ViewCommand::ExportGCode { path, per_pen })
+                        }
+                        FileSelector::ExportTiles(path, config) => {
+                            self.yolo_view_command(ViewCommand::ExportTiles { path, config })
+                        }
                     }
                     self.file_selector = None; // Delete it now that the command is done.
                 }
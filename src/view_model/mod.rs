@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::f32;
 use std::path::PathBuf;
 use std::process::exit;
@@ -16,11 +16,14 @@ use egui_toast::{Toast, ToastKind, ToastOptions};
 use rfd::FileDialog;
 use uuid::Uuid;
 
-use crate::core::commands::{ApplicationStateChangeMsg, ViewCommand};
+use crate::core::commands::{ApplicationStateChangeMsg, MarksConfig, TileConfig, ViewCommand};
 use crate::core::config::{AppConfig, DockPosition, RulerOrigin};
+use crate::core::estimate::PlotEstimate;
 use crate::core::machine::MachineConfig;
-use crate::core::project::{Orientation, PaperSize, PenDetail};
+use crate::core::project::{Orientation, PaperSize, PenDetail, PenPostSettings};
+use crate::core::resume::ResumeFrom;
 use crate::core::sender::{PlotterResponse, PlotterState};
+use crate::core::status::MachineStatus;
 use view_model_patch::ViewModelPatch;
 pub(crate) mod command_context;
 pub(crate) mod default;
@@ -53,6 +56,8 @@ pub enum FileSelector {
     SaveProjectAs(PathBuf),
     SaveMachineAs(PathBuf),
     LoadMachineFrom(PathBuf),
+    ExportGCode(PathBuf, bool),
+    ExportTiles(PathBuf, TileConfig),
     //SaveProject,
 }
 
@@ -61,6 +66,7 @@ pub struct BAPGeoLayer {
     pub name: String,
     pub preview: TextureHandle,
     pub pen_uuid: Uuid,
+    pub pinned: bool,
 }
 
 impl std::fmt::Debug for BAPGeoLayer {
@@ -68,6 +74,7 @@ impl std::fmt::Debug for BAPGeoLayer {
         f.debug_struct("BAPGeoLayer")
             .field("name", &self.name)
             .field("pen_uuid", &self.pen_uuid)
+            .field("pinned", &self.pinned)
             .finish()
     }
 }
@@ -136,8 +143,11 @@ pub struct BAPViewModel {
     move_increment: f32,
     join_handle: Option<JoinHandle<()>>,
     plotter_state: PlotterState,
+    machine_status: Option<MachineStatus>,
     queued_toasts: VecDeque<Toast>,
     pen_crib: Vec<PenDetail>,
+    pen_post: HashMap<Uuid, PenPostSettings>,
+    marks: Option<MarksConfig>,
     cancel_render: Option<Sender<()>>,
     undo_available: bool,
     file_path: Option<PathBuf>,
@@ -147,6 +157,7 @@ pub struct BAPViewModel {
     visuals: (String, Visuals),
     modifiers: Modifiers,
     gcode: String,
+    plot_estimate: Option<PlotEstimate>,
     geo_layers: Vec<BAPGeoLayer>,
     misc_textures: Option<MiscTextures>,
 }
@@ -302,7 +313,7 @@ impl BAPViewModel {
             // self.geo_layers = geo_layers;
             let mut new_layers = Vec::new();
             for idx in 0..geo_layers.len() {
-                let (name, preview, pen_uuid) = geo_layers.remove(0);
+                let (name, preview, pen_uuid, pinned) = geo_layers.remove(0);
                 let handle = ctx.load_texture(
                     format!("layer-img-{}", idx),
                     *preview,
@@ -313,6 +324,7 @@ impl BAPViewModel {
                     name,
                     preview: handle,
                     pen_uuid,
+                    pinned,
                 });
             }
             self.geo_layers = new_layers;
@@ -320,6 +332,12 @@ impl BAPViewModel {
         if let Some(pens) = patch.pens {
             self.pen_crib = pens
         }
+        if let Some(pen_post) = patch.pen_post {
+            self.pen_post = pen_post
+        }
+        if let Some(marks) = patch.marks {
+            self.marks = marks
+        }
         if let Some(paper) = patch.paper {
             self.paper_size = paper.size;
             self.paper_color = Color32::from_rgb(
@@ -402,6 +420,25 @@ impl BAPViewModel {
         }
     }
 
+    pub fn pen_post_settings(&self, pen_uuid: Uuid) -> PenPostSettings {
+        self.pen_post.get(&pen_uuid).cloned().unwrap_or_default()
+    }
+
+    pub fn update_pen_post_settings(&mut self, pen_uuid: Uuid, settings: PenPostSettings) {
+        self.pen_post.insert(pen_uuid, settings.clone());
+        self.yolo_view_command(ViewCommand::ApplyPenPostSettings(pen_uuid, settings));
+    }
+
+    pub fn marks(&self) -> Option<MarksConfig> {
+        self.marks.clone()
+    }
+
+    /// Sets the project's crop and registration marks, or removes them.
+    pub fn set_marks(&mut self, marks: Option<MarksConfig>) {
+        self.marks = marks.clone();
+        self.yolo_view_command(ViewCommand::SetMarks(marks));
+    }
+
     pub fn scale_by_factor(&mut self, factor: f64) {
         if let Some(cmd_out) = &self.cmd_out {
             cmd_out
@@ -460,6 +497,15 @@ impl BAPViewModel {
         }
     }
 
+    /// Starts the plot part way through, from a line, geometry or point.
+    pub fn plot_resume(&self, from: ResumeFrom) {
+        if let Some(cmd_out) = &self.cmd_out {
+            cmd_out
+                .send(ViewCommand::ResumePlot(from))
+                .expect("Failed to send Resume Plot command?");
+        }
+    }
+
     pub fn plot_pause(&self) {
         if let Some(cmd_out) = &self.cmd_out {
             cmd_out
@@ -510,13 +556,13 @@ impl BAPViewModel {
     /// Just checks if the source image is too big for either paper or machine.
     fn warn_if_bigger_than_available(&mut self) {
         if let Some(extents) = self.source_image_extents {
-            if extents.height() > self.machine_config.limits().1 as f32 {
+            if extents.height() > self.machine_config.bed_size().1 as f32 {
                 self.toast_warning(
                     "Source image is taller than machine extents. Trying flipping or scaling?"
                         .to_string(),
                 );
             }
-            if extents.width() > self.machine_config.limits().0 as f32 {
+            if extents.width() > self.machine_config.bed_size().0 as f32 {
                 self.toast_warning(
                     "Source image is wider than machine extents. Trying flipping or scaling?"
                         .to_string(),
@@ -527,8 +573,7 @@ impl BAPViewModel {
 
     pub fn center_machine(&mut self) {
         if let Some(extents) = self.source_image_extents {
-            let avail_width = self.machine_config.limits().0;
-            let avail_height = self.machine_config.limits().1;
+            let (avail_width, avail_height) = self.machine_config.bed_size();
 
             let left_gap = (avail_width as f32 - extents.width()) / 2.;
             let bottom_gap = (avail_height as f32 - extents.height()) / 2.;
@@ -858,8 +903,25 @@ impl BAPViewModel {
             PlotterResponse::Err(plotter_command, msg) => {
                 self.toast_error(format!("{:?} : {}", plotter_command, msg).to_string())
             }
+            PlotterResponse::Status(status) => {
+                // Redraw the plot preview's crosshair when the pen moves.
+                let moved = self
+                    .machine_status
+                    .as_ref()
+                    .is_none_or(|last| last.position != status.position);
+                if moved
+                    && self.display_mode == BAPDisplayMode::Plot
+                    && self.timeout_for_source_image.is_none()
+                {
+                    self.request_new_source_image();
+                }
+                self.machine_status = Some(status);
+            }
             PlotterResponse::State(plotter_state) => {
                 self.plotter_state = plotter_state.clone();
+                if plotter_state == PlotterState::Disconnected {
+                    self.machine_status = None;
+                }
                 // println!("Got plotter state: {:?}", plotter_state);
                 match &plotter_state {
                     PlotterState::Running(lines, oflines, _) => {
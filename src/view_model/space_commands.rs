@@ -4,7 +4,7 @@ use egui::{Key, mutex::Mutex};
 use indexmap::IndexMap;
 
 use crate::{
-    core::commands::{MatTarget, ViewCommand},
+    core::commands::{MatTarget, TileConfig, ViewCommand},
     view_model::{BAPViewModel, CommandContext},
 };
 
@@ -130,6 +130,44 @@ pub static SPACE_CMDS: LazyLock<Mutex<SpaceCommandBranch>> = LazyLock::new(|| {
         ),
     );
 
+    let cmd_export_gcode = (
+        Key::E,
+        (
+            "Export G-code".to_string(),
+            SpaceCommandBranch::Leaf(
+                "Export G-code".to_string(),
+                Box::new(|model| model.export_gcode_with_dialog(false)),
+                Some(Box::new(|model| !model.gcode().is_empty())),
+            ),
+        ),
+    );
+
+    let cmd_export_gcode_per_pen = (
+        Key::P,
+        (
+            "Export G-code per Pen".to_string(),
+            SpaceCommandBranch::Leaf(
+                "Export G-code per Pen".to_string(),
+                Box::new(|model| model.export_gcode_with_dialog(true)),
+                Some(Box::new(|model| !model.gcode().is_empty())),
+            ),
+        ),
+    );
+
+    let cmd_export_tiles = (
+        Key::T,
+        (
+            "Export Tiles".to_string(),
+            SpaceCommandBranch::Leaf(
+                "Export Tiles".to_string(),
+                Box::new(|model| {
+                    model.set_command_context(CommandContext::Tile(TileConfig::default()))
+                }),
+                Some(Box::new(|model| model.geo_layers().len() > 0)),
+            ),
+        ),
+    );
+
     let cmd_project_new = (
         Key::N,
         (
@@ -156,6 +194,10 @@ pub static SPACE_CMDS: LazyLock<Mutex<SpaceCommandBranch>> = LazyLock::new(|| {
                 cmd_load_pgf,
                 cmd_import_svg,
                 scb_separator(),
+                cmd_export_gcode,
+                cmd_export_gcode_per_pen,
+                cmd_export_tiles,
+                scb_separator(),
                 cmd_quit,
             ])),
         ),
@@ -299,6 +341,22 @@ pub static SPACE_CMDS: LazyLock<Mutex<SpaceCommandBranch>> = LazyLock::new(|| {
         ),
     );
 
+    let cmd_crop_marks = (
+        Key::K,
+        (
+            "Crop Marks".to_string(),
+            SpaceCommandBranch::Leaf(
+                "Crop Marks".to_string(),
+                Box::new(|model| {
+                    model.set_command_context(CommandContext::Marks(
+                        model.marks().unwrap_or_default(),
+                    ))
+                }),
+                Some(Box::new(|model| model.geo_layers().len() > 0)),
+            ),
+        ),
+    );
+
     let cmd_set_origin = (
         Key::O,
         (
@@ -417,6 +475,7 @@ pub static SPACE_CMDS: LazyLock<Mutex<SpaceCommandBranch>> = LazyLock::new(|| {
                 cmd_arrange_machine,
                 cmd_arrange_paper,
                 cmd_scale_to_mat,
+                cmd_crop_marks,
                 scb_separator(),
                 cmd_set_origin,
                 scb_separator(),
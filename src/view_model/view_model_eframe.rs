@@ -6,6 +6,7 @@ use egui::{Pos2, Vec2};
 use egui_toast::{Toast, ToastKind, ToastOptions};
 
 use crate::core::commands::ApplicationStateChangeMsg;
+use crate::core::validate::ValidationError;
 use crate::view_model::MiscTextures;
 
 use super::BAPDisplayMode;
@@ -157,6 +158,31 @@ impl eframe::App for BAPViewModel {
                     // self.display_mode = BAPDisplayMode::Plot;
                     self.set_display_mode(BAPDisplayMode::Plot);
                 }
+                ApplicationStateChangeMsg::PlotEstimate(estimate) => {
+                    self.queued_toasts.push_back(Toast {
+                        kind: ToastKind::Info,
+                        text: format!("{}", estimate).into(),
+                        options: ToastOptions::default().duration_in_seconds(15.),
+                        ..Default::default()
+                    });
+                    self.set_plot_estimate(Some(estimate));
+                }
+                ApplicationStateChangeMsg::PostViolations(violations) => {
+                    self.queued_toasts.push_back(Toast {
+                        kind: ToastKind::Warning,
+                        text: format!("{}", ValidationError { violations }).into(),
+                        options: ToastOptions::default().duration_in_seconds(15.),
+                        ..Default::default()
+                    });
+                }
+                ApplicationStateChangeMsg::PostSummary(summary) => {
+                    self.queued_toasts.push_back(Toast {
+                        kind: ToastKind::Info,
+                        text: format!("{}", summary).into(),
+                        options: ToastOptions::default().duration_in_seconds(15.),
+                        ..Default::default()
+                    });
+                }
                 ApplicationStateChangeMsg::Error(msg) => self.queued_toasts.push_back(Toast {
                     kind: ToastKind::Error,
                     text: msg.into(),
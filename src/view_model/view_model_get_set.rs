@@ -8,8 +8,10 @@ use crate::{
     core::{
         commands::{MatTarget, ViewCommand},
         config::{AppConfig, DockPosition, RulerOrigin},
+        estimate::PlotEstimate,
         machine::MachineConfig,
         sender::PlotterState,
+        status::MachineStatus,
     },
     view_model::BAPGeoLayer,
 };
@@ -50,6 +52,10 @@ impl BAPViewModel {
         self.yolo_view_command(ViewCommand::RenameLayer { id: idx, name });
     }
 
+    pub fn update_layer_pinned(&mut self, idx: usize, pinned: bool) {
+        self.yolo_view_command(ViewCommand::PinLayer { id: idx, pinned });
+    }
+
     pub fn geo_layers_mut(&mut self) -> &mut Vec<BAPGeoLayer> {
         &mut self.geo_layers
     }
@@ -82,6 +88,14 @@ impl BAPViewModel {
         &mut self.gcode
     }
 
+    pub fn plot_estimate(&self) -> Option<PlotEstimate> {
+        self.plot_estimate.clone()
+    }
+
+    pub fn set_plot_estimate(&mut self, plot_estimate: Option<PlotEstimate>) {
+        self.plot_estimate = plot_estimate;
+    }
+
     pub fn set_gcode(&mut self, gcode: String) {
         self.gcode = gcode;
     }
@@ -171,6 +185,11 @@ impl BAPViewModel {
         self.plotter_state.clone()
     }
 
+    /// The last status report from a GRBL plotter, if there is one.
+    pub fn machine_status(&self) -> Option<MachineStatus> {
+        self.machine_status.clone()
+    }
+
     pub fn set_move_increment(&mut self, increment: f32) {
         self.move_increment = increment;
     }
@@ -4,21 +4,24 @@ use egui::ColorImage;
 use uuid::Uuid;
 
 use crate::core::{
+    commands::MarksConfig,
     machine::MachineConfig,
-    project::{Paper, PenDetail, Project},
+    project::{Paper, PenDetail, PenPostSettings, Project},
     render_preview::render_layer_preview,
 };
 
 #[derive(Default, Clone, PartialEq)]
 pub(crate) struct ViewModelPatch {
     pub pens: Option<Vec<PenDetail>>,
+    pub pen_post: Option<HashMap<Uuid, PenPostSettings>>,
+    pub marks: Option<Option<MarksConfig>>,
     pub paper: Option<Paper>,
     pub origin: Option<Option<(f64, f64)>>, // Target/center of the viewport
     pub extents: Option<(f64, f64, f64, f64)>,
     pub machine_config: Option<Option<MachineConfig>>,
     pub program: Option<Option<Box<Vec<String>>>>,
     pub file_path: Option<Option<PathBuf>>,
-    pub geo_layers: Option<Vec<(String, Box<ColorImage>, Uuid)>>, //Option<Vec<(String, Box<ColorImage>, Uuid)>>,
+    pub geo_layers: Option<Vec<(String, Box<ColorImage>, Uuid, bool)>>, //Option<Vec<(String, Box<ColorImage>, Uuid)>>,
 }
 
 impl std::fmt::Debug for ViewModelPatch {
@@ -29,6 +32,8 @@ impl std::fmt::Debug for ViewModelPatch {
         };
         f.debug_struct("ViewModelPatch")
             .field("pens", &self.pens)
+            .field("pen_post", &self.pen_post)
+            .field("marks", &self.marks)
             .field("paper", &self.paper)
             .field("origin", &self.origin)
             .field("extents", &self.extents)
@@ -49,6 +54,8 @@ impl From<Project> for ViewModelPatch {
             HashMap::from_iter(pens.iter().map(|pen| (pen.identity, pen.clone())));
         Self {
             pens: Some(project.pens.clone()),
+            pen_post: Some(project.pen_post.clone()),
+            marks: Some(project.marks.clone()),
             paper: Some(project.paper.clone()),
             origin: Some(project.origin.clone()),
             extents: Some((
@@ -80,6 +87,7 @@ impl From<Project> for ViewModelPatch {
                             // ColorImage::filled([32, 32], Color32::LIGHT_GRAY),
                             // .unwrap_or(ColorImage::filled([32, 32], Color32::LIGHT_GRAY)),
                             item.pen_uuid.clone(),
+                            item.pinned,
                         )
                     })
                     .collect(),